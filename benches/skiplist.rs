@@ -0,0 +1,251 @@
+//! Compares `SkipList` with `BTreeMap`, crossbeam's `SkipSet` and a
+//! `BTreeSet` behind an `RwLock`.
+//!
+//! Run with `cargo bench`, or `cargo bench -- lookup` for one group. Criterion
+//! keeps the last results in `target/criterion` and reports changes against
+//! them.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use criterion::{black_box, criterion_group, criterion_main, Bencher, BenchmarkId, Criterion, Throughput};
+use crossbeam_skiplist::SkipSet;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use skiplist_rust::arena::Arena;
+use skiplist_rust::SkipList;
+
+const SIZES: [u64; 3] = [1_000, 10_000, 100_000];
+// Keys looked up per iteration of the lookup benchmarks.
+const LOOKUPS: u64 = 1_000;
+
+fn shuffled(n: u64) -> Vec<u64> {
+    let mut keys: Vec<u64> = (0..n).collect();
+    keys.shuffle(&mut StdRng::seed_from_u64(42));
+    keys
+}
+
+/// Every other key, so that half of the lookups miss.
+fn stored_keys(n: u64) -> Vec<u64> {
+    shuffled(n).into_iter().map(|k| k * 2).collect()
+}
+
+fn probes(n: u64) -> Vec<u64> {
+    shuffled(2 * n).into_iter().take(LOOKUPS as usize).collect()
+}
+
+fn skiplist(keys: &[u64]) -> SkipList<u64> {
+    let list = SkipList::new(Arena::new());
+    for &k in keys {
+        list.insert(k);
+    }
+    list
+}
+
+fn insert(c: &mut Criterion) {
+    for (name, random) in [("insert_sequential", false), ("insert_random", true)] {
+        let mut group = c.benchmark_group(name);
+        for n in SIZES {
+            let keys = if random { shuffled(n) } else { (0..n).collect() };
+            group.throughput(Throughput::Elements(n));
+            group.bench_with_input(BenchmarkId::new("SkipList", n), &keys, |b, keys| b.iter(|| skiplist(keys)));
+            group.bench_with_input(BenchmarkId::new("BTreeMap", n), &keys, |b, keys| {
+                b.iter(|| {
+                    let mut map = BTreeMap::new();
+                    for &k in keys {
+                        map.insert(k, ());
+                    }
+                    map
+                })
+            });
+            group.bench_with_input(BenchmarkId::new("SkipSet", n), &keys, |b, keys| {
+                b.iter(|| {
+                    let set = SkipSet::new();
+                    for &k in keys {
+                        set.insert(k);
+                    }
+                    set
+                })
+            });
+            group.bench_with_input(BenchmarkId::new("RwLock<BTreeSet>", n), &keys, |b, keys| {
+                b.iter(|| {
+                    let set = RwLock::new(BTreeSet::new());
+                    for &k in keys {
+                        set.write().unwrap().insert(k);
+                    }
+                    set
+                })
+            });
+        }
+        group.finish();
+    }
+}
+
+fn lookup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lookup");
+    group.throughput(Throughput::Elements(LOOKUPS));
+    for n in SIZES {
+        let keys = stored_keys(n);
+        let probes = probes(n);
+
+        let list = skiplist(&keys);
+        group.bench_with_input(BenchmarkId::new("SkipList", n), &probes, |b, probes| {
+            b.iter(|| probes.iter().filter(|k| list.contains(k)).count())
+        });
+        let map: BTreeMap<u64, ()> = keys.iter().map(|&k| (k, ())).collect();
+        group.bench_with_input(BenchmarkId::new("BTreeMap", n), &probes, |b, probes| {
+            b.iter(|| probes.iter().filter(|k| map.contains_key(k)).count())
+        });
+        let set: SkipSet<u64> = keys.iter().copied().collect();
+        group.bench_with_input(BenchmarkId::new("SkipSet", n), &probes, |b, probes| {
+            b.iter(|| probes.iter().filter(|k| set.contains(k)).count())
+        });
+        let locked = RwLock::new(keys.iter().copied().collect::<BTreeSet<u64>>());
+        group.bench_with_input(BenchmarkId::new("RwLock<BTreeSet>", n), &probes, |b, probes| {
+            b.iter(|| probes.iter().filter(|k| locked.read().unwrap().contains(k)).count())
+        });
+    }
+    group.finish();
+}
+
+fn scan(c: &mut Criterion) {
+    for (name, reverse) in [("scan_forward", false), ("scan_reverse", true)] {
+        let mut group = c.benchmark_group(name);
+        for n in SIZES {
+            let keys = shuffled(n);
+            group.throughput(Throughput::Elements(n));
+
+            let list = skiplist(&keys);
+            group.bench_function(BenchmarkId::new("SkipList", n), |b| {
+                b.iter(|| {
+                    let mut sum = 0u64;
+                    let mut iter = list.iter();
+                    if reverse {
+                        iter.seek_to_last();
+                    } else {
+                        iter.seek_to_first();
+                    }
+                    while iter.valid() {
+                        sum = sum.wrapping_add(*iter.key());
+                        if reverse {
+                            iter.prev();
+                        } else {
+                            iter.next();
+                        }
+                    }
+                    sum
+                })
+            });
+            let map: BTreeMap<u64, ()> = keys.iter().map(|&k| (k, ())).collect();
+            group.bench_function(BenchmarkId::new("BTreeMap", n), |b| {
+                b.iter(|| {
+                    if reverse {
+                        map.keys().rev().fold(0u64, |s, k| s.wrapping_add(*k))
+                    } else {
+                        map.keys().fold(0u64, |s, k| s.wrapping_add(*k))
+                    }
+                })
+            });
+            let set: SkipSet<u64> = keys.iter().copied().collect();
+            group.bench_function(BenchmarkId::new("SkipSet", n), |b| {
+                b.iter(|| {
+                    if reverse {
+                        set.iter().rev().fold(0u64, |s, e| s.wrapping_add(*e))
+                    } else {
+                        set.iter().fold(0u64, |s, e| s.wrapping_add(*e))
+                    }
+                })
+            });
+            let locked = RwLock::new(keys.iter().copied().collect::<BTreeSet<u64>>());
+            group.bench_function(BenchmarkId::new("RwLock<BTreeSet>", n), |b| {
+                b.iter(|| {
+                    let set = locked.read().unwrap();
+                    if reverse {
+                        set.iter().rev().fold(0u64, |s, k| s.wrapping_add(*k))
+                    } else {
+                        set.iter().fold(0u64, |s, k| s.wrapping_add(*k))
+                    }
+                })
+            });
+        }
+        group.finish();
+    }
+}
+
+/// Criterion's default of 100 samples in 5 s gives samples of about this length.
+const SAMPLE_TIME: Duration = Duration::from_millis(50);
+
+/// Measures `read` on a structure of `n` keys while another thread inserts
+/// into it. Every sample starts from a fresh structure made by `build`, and
+/// the writer spreads `n / 10` inserts of keys above any stored or looked up
+/// key over about one sample, then stops. A structure therefore never grows
+/// by more than a tenth of its starting size.
+fn with_writer<S: Send + Sync + 'static>(
+    b: &mut Bencher,
+    n: u64,
+    build: impl Fn() -> S,
+    insert: fn(&S, u64),
+    read: impl Fn(&S) -> usize,
+) {
+    let inserts = n / 10;
+    let interval = SAMPLE_TIME / inserts as u32;
+    b.iter_custom(|iters| {
+        let structure = Arc::new(build());
+        let stop = Arc::new(AtomicBool::new(false));
+        let writer = {
+            let structure = Arc::clone(&structure);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                for k in 0..inserts {
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    insert(&structure, u64::MAX / 2 + k);
+                    thread::sleep(interval);
+                }
+            })
+        };
+        let start = Instant::now();
+        for _ in 0..iters {
+            black_box(read(&structure));
+        }
+        let elapsed = start.elapsed();
+        stop.store(true, Ordering::Relaxed);
+        writer.join().unwrap();
+        elapsed
+    });
+}
+
+fn concurrent_lookup(c: &mut Criterion) {
+    // BTreeMap has no concurrent counterpart to compare with.
+    let mut group = c.benchmark_group("lookup_during_writes");
+    group.throughput(Throughput::Elements(LOOKUPS));
+    for n in SIZES {
+        let keys = stored_keys(n);
+        let probes = probes(n);
+
+        group.bench_function(BenchmarkId::new("SkipList", n), |b| {
+            with_writer(b, n, || skiplist(&keys), |list, k| list.insert(k), |list| {
+                probes.iter().filter(|k| list.contains(k)).count()
+            })
+        });
+        group.bench_function(BenchmarkId::new("SkipSet", n), |b| {
+            with_writer(b, n, || keys.iter().copied().collect::<SkipSet<u64>>(), |set, k| {
+                set.insert(k);
+            }, |set| probes.iter().filter(|k| set.contains(k)).count())
+        });
+        group.bench_function(BenchmarkId::new("RwLock<BTreeSet>", n), |b| {
+            with_writer(b, n, || RwLock::new(keys.iter().copied().collect::<BTreeSet<u64>>()), |set, k| {
+                set.write().unwrap().insert(k);
+            }, |set| probes.iter().filter(|k| set.read().unwrap().contains(k)).count())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, insert, lookup, scan, concurrent_lookup);
+criterion_main!(benches);
@@ -0,0 +1,122 @@
+//! Runs a sequence of allocations, reserves and resets on an arena and
+//! checks that live allocations never overlap or get overwritten.
+
+#![no_main]
+
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+use skiplist_rust::arena::{Arena, ArenaOptions, GrowthPolicy};
+use skiplist_rust::error::Error;
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    Allocate(u16),
+    AllocateAligned(u16),
+    Reserve(u16),
+    Reset,
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    block_size: u16,
+    geometric: bool,
+    limit: Option<u32>,
+    max_retained: Option<u32>,
+    ops: Vec<Op>,
+}
+
+struct Allocation {
+    ptr: *mut u8,
+    len: usize,
+    fill: u8,
+}
+
+impl Allocation {
+    fn bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+
+    fn overlaps(&self, other: &Allocation) -> bool {
+        let (a, b) = (self.ptr as usize, other.ptr as usize);
+        a < b + other.len && b < a + self.len
+    }
+}
+
+fuzz_target!(|input: Input| {
+    let block_size = input.block_size.max(1) as usize;
+    let growth = if input.geometric {
+        GrowthPolicy::Geometric { max_block_size: block_size * 16 }
+    } else {
+        GrowthPolicy::Fixed
+    };
+    let options = ArenaOptions {
+        block_size,
+        growth,
+        limit: input.limit.map(|l| l as usize),
+        max_retained: input.max_retained.map(|r| r as usize),
+    };
+    let mut arena = Arena::with_options(options);
+    let mut live: Vec<Allocation> = Vec::new();
+    // Bytes promised by the last successful `reserve`.
+    let mut reserved = 0;
+
+    for (i, op) in input.ops.into_iter().enumerate() {
+        let usage = arena.memory_usage();
+        let result = match op {
+            Op::Allocate(bytes) => arena.try_allocate(bytes.max(1) as usize).map(|p| (p, bytes.max(1), false)),
+            Op::AllocateAligned(bytes) => {
+                arena.try_allocate_aligned(bytes.max(1) as usize).map(|p| (p, bytes.max(1), true))
+            }
+            Op::Reserve(bytes) => {
+                if arena.reserve(bytes as usize).is_ok() {
+                    reserved = bytes as usize;
+                }
+                continue;
+            }
+            Op::Reset => {
+                for a in &live {
+                    assert!(a.bytes().iter().all(|&b| b == a.fill));
+                }
+                live.clear();
+                reserved = 0;
+                arena.reset();
+                assert_eq!(arena.stats().allocated_bytes, 0);
+                continue;
+            }
+        };
+
+        if let Some(limit) = options.limit {
+            assert!(arena.memory_usage() <= limit);
+        }
+        let (ptr, len, aligned) = match result {
+            Ok(allocation) => allocation,
+            Err(Error::ArenaFull) => continue,
+            Err(e) => panic!("unexpected error: {}", e),
+        };
+        let len = len as usize;
+        assert!(!ptr.is_null());
+        if aligned {
+            assert_eq!(ptr as usize % 8, 0);
+            // Alignment padding counts against the reservation too.
+            reserved = 0;
+        } else if len <= reserved {
+            assert_eq!(arena.memory_usage(), usage, "allocation within a reservation took a new block");
+            reserved -= len;
+        } else {
+            reserved = 0;
+        }
+
+        let allocation = Allocation { ptr, len, fill: i as u8 };
+        assert!(live.iter().all(|a| !a.overlaps(&allocation)));
+        unsafe { std::ptr::write_bytes(ptr, allocation.fill, len) };
+        live.push(allocation);
+
+        let stats = arena.stats();
+        assert_eq!(stats.allocated_bytes, live.iter().map(|a| a.len).sum::<usize>());
+        assert!(stats.allocated_bytes + stats.alignment_waste <= stats.block_bytes);
+    }
+
+    for a in &live {
+        assert!(a.bytes().iter().all(|&b| b == a.fill));
+    }
+});
@@ -0,0 +1,130 @@
+//! Runs a sequence of operations on a list and one iterator over it, and
+//! compares every result with a `BTreeSet`.
+
+#![no_main]
+
+use std::collections::BTreeSet;
+use std::ops::Bound::{Excluded, Unbounded};
+
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+use skiplist_rust::arena::{Arena, ArenaOptions};
+use skiplist_rust::error::Error;
+use skiplist_rust::{SkipList, SkipListIterator};
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    Insert(u16),
+    Contains(u16),
+    Seek(u16),
+    SeekToFirst,
+    SeekToLast,
+    Next,
+    Prev,
+    // Keys in `[start, end)`, read with seek and next.
+    Range(u16, u16),
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    // Small blocks and a limit, so that nodes span blocks and inserts run
+    // into `ArenaFull`.
+    block_size: u16,
+    limit: Option<u16>,
+    ops: Vec<Op>,
+}
+
+fn range(list: &SkipList<u16>, start: u16, end: u16) -> Vec<u16> {
+    let mut keys = Vec::new();
+    let mut iter = list.iter();
+    iter.seek(&start);
+    while iter.valid() && *iter.key() < end {
+        keys.push(*iter.key());
+        iter.next();
+    }
+    keys
+}
+
+fn check_position(iter: &SkipListIterator<u16>, expected: Option<u16>) {
+    assert_eq!(iter.valid(), expected.is_some());
+    if let Some(key) = expected {
+        assert_eq!(*iter.key(), key);
+    }
+}
+
+fuzz_target!(|input: Input| {
+    let block_size = input.block_size.max(1) as usize;
+    let options = ArenaOptions {
+        block_size,
+        // Leave room for the block holding the head.
+        limit: input.limit.map(|l| 2 * block_size + 64 + l as usize),
+        ..ArenaOptions::default()
+    };
+    let list = SkipList::new(Arena::with_options(options));
+    let mut model = BTreeSet::new();
+    let mut iter = list.iter();
+    // Where `iter` is expected to be.
+    let mut position: Option<u16> = None;
+
+    for op in input.ops {
+        match op {
+            Op::Insert(key) => {
+                if model.contains(&key) {
+                    continue;
+                }
+                match list.try_insert(key) {
+                    Ok(()) => {
+                        model.insert(key);
+                    }
+                    Err(Error::ArenaFull) => {}
+                    Err(e) => panic!("unexpected error: {}", e),
+                }
+                assert_eq!(list.len(), model.len());
+            }
+            Op::Contains(key) => assert_eq!(list.contains(&key), model.contains(&key)),
+            Op::Seek(key) => {
+                iter.seek(&key);
+                position = model.range(key..).next().copied();
+                check_position(&iter, position);
+            }
+            Op::SeekToFirst => {
+                iter.seek_to_first();
+                position = model.first().copied();
+                check_position(&iter, position);
+            }
+            Op::SeekToLast => {
+                iter.seek_to_last();
+                position = model.last().copied();
+                check_position(&iter, position);
+            }
+            // The iterator follows the list as it is now, so keys inserted
+            // next to its position since it moved there are visited.
+            Op::Next => {
+                if let Some(key) = position {
+                    iter.next();
+                    position = model.range((Excluded(key), Unbounded)).next().copied();
+                    check_position(&iter, position);
+                }
+            }
+            Op::Prev => {
+                if let Some(key) = position {
+                    iter.prev();
+                    position = model.range(..key).next_back().copied();
+                    check_position(&iter, position);
+                }
+            }
+            Op::Range(start, end) => {
+                let expected: Vec<u16> = model.iter().copied().filter(|k| (start..end).contains(k)).collect();
+                assert_eq!(range(&list, start, end), expected);
+            }
+        }
+    }
+
+    let mut keys = Vec::new();
+    iter.seek_to_first();
+    while iter.valid() {
+        keys.push(*iter.key());
+        iter.next();
+    }
+    assert!(keys.into_iter().eq(model));
+});
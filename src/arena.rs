@@ -1,13 +1,91 @@
+use std::alloc::Layout;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::ptr;
 
+use crate::error::{Error, Result};
+use crate::stats::MemoryStats;
+
 const BLOCK_SIZE: usize = 4096;
+const BLOCK_ALIGN: usize = if std::mem::size_of::<*mut ()>() > 8 { std::mem::size_of::<*mut ()>() } else { 8 };
+
+/// The source of the blocks an `Arena` carves allocations from.
+///
+/// # Safety
+///
+/// `allocate` must return either null or a pointer to memory that fits
+/// `layout` and stays valid until it is passed to `deallocate`.
+pub unsafe trait BlockAllocator {
+    /// Returns a new block, or null if none is available.
+    fn allocate(&self, layout: Layout) -> *mut u8;
+
+    /// # Safety
+    ///
+    /// `ptr` must come from `allocate` on this allocator with the same layout.
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout);
+}
+
+/// Takes blocks from the global allocator.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Global;
 
-pub struct Arena {
+unsafe impl BlockAllocator for Global {
+    fn allocate(&self, layout: Layout) -> *mut u8 {
+        unsafe { std::alloc::alloc(layout) }
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+        std::alloc::dealloc(ptr, layout)
+    }
+}
+
+/// How the size of successive blocks changes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GrowthPolicy {
+    /// Every block has the configured block size.
+    Fixed,
+    /// Each block is twice the size of the one before, up to `max_block_size`,
+    /// so a list that grows large needs only a few system allocations.
+    Geometric { max_block_size: usize },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ArenaOptions {
+    /// Size of the first block. Allocations larger than a quarter of the
+    /// current block size get a block of their own.
+    pub block_size: usize,
+    pub growth: GrowthPolicy,
+    /// Upper bound on `memory_usage`, see `Arena::with_limit`.
+    pub limit: Option<usize>,
+    /// How many bytes of blocks `Arena::reset` keeps for reuse. `None` keeps
+    /// them all.
+    pub max_retained: Option<usize>,
+}
+
+impl Default for ArenaOptions {
+    fn default() -> Self {
+        ArenaOptions { block_size: BLOCK_SIZE, growth: GrowthPolicy::Fixed, limit: None, max_retained: None }
+    }
+}
+
+struct Block {
+    ptr: *mut u8,
+    size: usize,
+    // Holds a single allocation too large to share a block.
+    large: bool,
+}
+
+pub struct Arena<A: BlockAllocator = Global> {
     alloc_ptr: *mut u8,
     alloc_bytes_remaining: usize,
-    blocks: Vec<*mut u8>,
+    blocks: Vec<Block>,
+    // Blocks kept by `reset` that are not in use yet.
+    free: Vec<Block>,
     memory_usage: AtomicUsize,
+    allocated_bytes: usize,
+    alignment_waste: usize,
+    block_size: usize,
+    options: ArenaOptions,
+    allocator: A,
 }
 
 impl Default for Arena {
@@ -18,34 +96,91 @@ impl Default for Arena {
 
 impl Arena {
     pub fn new() -> Self {
+        Self::with_options(ArenaOptions::default())
+    }
+
+    /// Creates an arena whose `memory_usage` never exceeds `limit` bytes.
+    /// Allocations that would cross it fail with `Error::ArenaFull`.
+    pub fn with_limit(limit: usize) -> Self {
+        Self::with_options(ArenaOptions { limit: Some(limit), ..ArenaOptions::default() })
+    }
+
+    /// Creates an arena that allocates blocks of `block_size` bytes.
+    pub fn with_block_size(block_size: usize) -> Self {
+        Self::with_options(ArenaOptions { block_size, ..ArenaOptions::default() })
+    }
+
+    pub fn with_options(options: ArenaOptions) -> Self {
+        Self::with_options_in(options, Global)
+    }
+}
+
+impl<A: BlockAllocator> Arena<A> {
+    /// Creates an arena that takes its blocks from `allocator`.
+    pub fn new_in(allocator: A) -> Self {
+        Self::with_options_in(ArenaOptions::default(), allocator)
+    }
+
+    pub fn with_options_in(options: ArenaOptions, allocator: A) -> Self {
+        assert!(options.block_size > 0);
+        if let GrowthPolicy::Geometric { max_block_size } = options.growth {
+            assert!(max_block_size >= options.block_size);
+        }
         Arena {
             alloc_ptr: ptr::null_mut(),
             alloc_bytes_remaining: 0,
             blocks: Vec::new(),
+            free: Vec::new(),
             memory_usage: AtomicUsize::new(0),
+            allocated_bytes: 0,
+            alignment_waste: 0,
+            block_size: options.block_size,
+            options,
+            allocator,
         }
     }
 
+    /// # Panics
+    ///
+    /// Panics if the memory limit is reached.
     pub fn allocate(&mut self, bytes: usize) -> *mut u8 {
+        self.try_allocate(bytes).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn allocate_aligned(&mut self, bytes: usize) -> *mut u8 {
+        self.try_allocate_aligned(bytes).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Like `allocate`, but returns `Error::ArenaFull` if the memory limit
+    /// would be exceeded or the system allocator is out of memory.
+    pub fn try_allocate(&mut self, bytes: usize) -> Result<*mut u8> {
         assert!(bytes > 0);
         if bytes <= self.alloc_bytes_remaining {
             unsafe {
                 let result = self.alloc_ptr;
                 self.alloc_ptr = self.alloc_ptr.add(bytes);
                 self.alloc_bytes_remaining -= bytes;
-                result
+                self.allocated_bytes += bytes;
+                Ok(result)
             }
         } else {
             self.allocate_fallback(bytes)
         }
     }
 
-    pub fn allocate_aligned(&mut self, bytes: usize) -> *mut u8 {
-        let align = if std::mem::size_of::<*mut ()>() > 8 {
-            std::mem::size_of::<*mut ()>()
-        } else {
-            8
-        };
+    pub fn try_allocate_aligned(&mut self, bytes: usize) -> Result<*mut u8> {
+        self.allocate_with_align(bytes, BLOCK_ALIGN)
+    }
+
+    /// Allocates memory for `layout`, padding to its alignment when it is
+    /// stricter than `allocate_aligned`'s. Returns `Error::ArenaFull` like
+    /// `try_allocate`.
+    pub fn try_allocate_layout(&mut self, layout: Layout) -> Result<*mut u8> {
+        self.allocate_with_align(layout.size(), layout.align().max(BLOCK_ALIGN))
+    }
+
+    fn allocate_with_align(&mut self, bytes: usize, align: usize) -> Result<*mut u8> {
+        assert!(bytes > 0);
         assert!(align.is_power_of_two());
 
         let current_mod = (self.alloc_ptr as usize) & (align - 1);
@@ -57,61 +192,163 @@ impl Arena {
                 let result = self.alloc_ptr.add(slop);
                 self.alloc_ptr = self.alloc_ptr.add(needed);
                 self.alloc_bytes_remaining -= needed;
-                result
+                self.allocated_bytes += bytes;
+                self.alignment_waste += slop;
+                Ok(result)
             }
-        } else {
+        } else if align <= BLOCK_ALIGN {
+            // New blocks are aligned, so the fallback result is too.
             self.allocate_fallback(bytes)
+        } else {
+            // New blocks are only aligned to BLOCK_ALIGN, so take enough
+            // extra room to pad the result to `align`.
+            let pad = align - BLOCK_ALIGN;
+            let ptr = self.allocate_fallback(bytes + pad)?;
+            let slop = (ptr as usize).wrapping_neg() & (align - 1);
+            self.allocated_bytes -= pad;
+            self.alignment_waste += pad;
+            Ok(unsafe { ptr.add(slop) })
+        }
+    }
+
+    /// Makes sure the next `bytes` bytes of allocations, large ones included,
+    /// are served from memory that is already allocated. The rest of the
+    /// current block is abandoned if it is too small.
+    pub fn reserve(&mut self, bytes: usize) -> Result<()> {
+        if bytes <= self.alloc_bytes_remaining {
+            return Ok(());
         }
+        let (ptr, size) = self.allocate_new_block(bytes.max(self.block_size), false)?;
+        self.alloc_ptr = ptr;
+        self.alloc_bytes_remaining = size;
+        Ok(())
     }
 
+    /// Takes back every allocation at once, without returning the blocks to
+    /// the system allocator. Up to `ArenaOptions::max_retained` bytes of them
+    /// are kept and handed out again before any new block is allocated.
+    ///
+    /// Pointers returned before the reset must no longer be used.
+    pub fn reset(&mut self) {
+        let mut retained = 0;
+        let max_retained = self.options.max_retained.unwrap_or(usize::MAX);
+        for block in self.blocks.drain(..).chain(self.free.drain(..)).collect::<Vec<_>>() {
+            if retained + block.size <= max_retained {
+                retained += block.size;
+                self.free.push(block);
+            } else {
+                self.memory_usage.fetch_sub(block.size + std::mem::size_of::<Block>(), Ordering::Relaxed);
+                self.dealloc(block);
+            }
+        }
+        self.alloc_ptr = ptr::null_mut();
+        self.alloc_bytes_remaining = 0;
+        self.allocated_bytes = 0;
+        self.alignment_waste = 0;
+        self.block_size = self.options.block_size;
+    }
+
+    /// Total size of the blocks allocated so far plus their bookkeeping,
+    /// including space that has not been handed out yet.
     pub fn memory_usage(&self) -> usize {
         self.memory_usage.load(Ordering::Relaxed)
     }
 
-    fn allocate_fallback(&mut self, bytes: usize) -> *mut u8 {
-        if bytes > BLOCK_SIZE / 4 {
-            return self.allocate_new_block(bytes);
+    /// A breakdown of `memory_usage`. The fields that describe a skip list
+    /// are left at zero; `SkipList::stats` fills them in.
+    pub fn stats(&self) -> MemoryStats {
+        let held = self.blocks.iter().chain(&self.free);
+        MemoryStats {
+            block_bytes: held.clone().map(|b| b.size).sum(),
+            bookkeeping_bytes: (self.blocks.len() + self.free.len()) * std::mem::size_of::<Block>(),
+            allocated_bytes: self.allocated_bytes,
+            alignment_waste: self.alignment_waste,
+            blocks: self.blocks.len() + self.free.len(),
+            large_blocks: held.filter(|b| b.large).count(),
+            ..MemoryStats::default()
+        }
+    }
+
+    fn allocate_fallback(&mut self, bytes: usize) -> Result<*mut u8> {
+        if bytes > self.block_size / 4 {
+            let (ptr, _) = self.allocate_new_block(bytes, true)?;
+            self.allocated_bytes += bytes;
+            return Ok(ptr);
         }
 
-        self.alloc_ptr = self.allocate_new_block(BLOCK_SIZE);
-        self.alloc_bytes_remaining = BLOCK_SIZE;
+        // On failure the current block is kept for smaller allocations.
+        let (ptr, size) = self.allocate_new_block(self.block_size, false)?;
+        self.alloc_ptr = ptr;
+        self.alloc_bytes_remaining = size;
+        if let GrowthPolicy::Geometric { max_block_size } = self.options.growth {
+            self.block_size = self.block_size.saturating_mul(2).min(max_block_size);
+        }
 
         let result = self.alloc_ptr;
         unsafe {
             self.alloc_ptr = self.alloc_ptr.add(bytes);
         }
         self.alloc_bytes_remaining -= bytes;
-        result
+        self.allocated_bytes += bytes;
+        Ok(result)
+    }
+
+    /// Returns a block of at least `block_bytes` and its actual size, which
+    /// is larger when a retained block is reused.
+    fn allocate_new_block(&mut self, block_bytes: usize, large: bool) -> Result<(*mut u8, usize)> {
+        // Best fit, so that small requests leave the large blocks for large ones.
+        let reusable = self.free.iter().enumerate().filter(|(_, b)| b.size >= block_bytes);
+        if let Some((i, _)) = reusable.min_by_key(|(_, b)| b.size) {
+            let mut block = self.free.swap_remove(i);
+            block.large = large;
+            let result = (block.ptr, block.size);
+            self.blocks.push(block);
+            return Ok(result);
+        }
+
+        let charge = block_bytes + std::mem::size_of::<Block>();
+        let limit = self.options.limit.unwrap_or(usize::MAX);
+        if self.memory_usage().checked_add(charge).map_or(true, |usage| usage > limit) {
+            return Err(Error::ArenaFull);
+        }
+        let layout = Layout::from_size_align(block_bytes, BLOCK_ALIGN).map_err(|_| Error::ArenaFull)?;
+        let result = self.allocator.allocate(layout);
+        if result.is_null() {
+            return Err(Error::ArenaFull);
+        }
+        self.blocks.push(Block { ptr: result, size: block_bytes, large });
+        self.memory_usage.fetch_add(charge, Ordering::Relaxed);
+        Ok((result, block_bytes))
     }
 
-    fn allocate_new_block(&mut self, block_bytes: usize) -> *mut u8 {
-        let result = unsafe {
-            let layout = std::alloc::Layout::from_size_align(block_bytes, 1).unwrap();
-            std::alloc::alloc(layout)
-        };
-        self.blocks.push(result);
-        self.memory_usage.fetch_add(block_bytes + std::mem::size_of::<*mut u8>(), Ordering::Relaxed);
-        result
+    fn dealloc(&self, block: Block) {
+        unsafe {
+            let layout = Layout::from_size_align(block.size, BLOCK_ALIGN).unwrap();
+            self.allocator.deallocate(block.ptr, layout);
+        }
     }
 }
 
-impl Drop for Arena {
+impl<A: BlockAllocator> Drop for Arena<A> {
     fn drop(&mut self) {
-        for &block in &self.blocks {
-            unsafe {
-                let layout = std::alloc::Layout::from_size_align(BLOCK_SIZE, 1).unwrap();
-                std::alloc::dealloc(block, layout);
-            }
+        let blocks: Vec<Block> = self.blocks.drain(..).chain(self.free.drain(..)).collect();
+        for block in blocks {
+            self.dealloc(block);
         }
     }
 }
 
 #[cfg(test)]
 mod test {
+    use std::alloc::Layout;
     use std::cmp;
+    use std::mem::size_of;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
     use rand::prelude::StdRng;
     use rand::{Rng, SeedableRng};
-    use crate::arena::Arena;
+    use crate::arena::{Arena, ArenaOptions, Block, BlockAllocator, Global, GrowthPolicy, BLOCK_SIZE};
+    use crate::error::Error;
 
     #[test]
     fn test_arena_empty() {
@@ -169,4 +406,205 @@ mod test {
             }
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_arena_limit() {
+        let limit = 4 * BLOCK_SIZE;
+        let mut arena = Arena::with_limit(limit);
+        let mut allocated = 0;
+        loop {
+            match arena.try_allocate(100) {
+                Ok(_) => allocated += 100,
+                Err(Error::ArenaFull) => break,
+                Err(e) => panic!("{}", e),
+            }
+        }
+        assert!(arena.memory_usage() <= limit);
+        assert!(allocated >= 2 * BLOCK_SIZE);
+
+        // A failed allocation keeps the tail of the current block usable.
+        let usage = arena.memory_usage();
+        assert!(matches!(arena.try_allocate(BLOCK_SIZE), Err(Error::ArenaFull)));
+        assert!(arena.try_allocate(BLOCK_SIZE % 100 / 2).is_ok());
+        assert_eq!(arena.memory_usage(), usage);
+    }
+
+    #[test]
+    fn test_arena_block_size() {
+        let mut arena = Arena::with_block_size(64 * 1024);
+        let p = arena.allocate(10000);
+        let q = arena.allocate(10000);
+        // Both fit in the first block, below the large allocation threshold.
+        assert_eq!(unsafe { p.add(10000) }, q);
+        assert_eq!(arena.memory_usage(), 64 * 1024 + size_of::<Block>());
+
+        // Too big for the rest of the block and above the threshold.
+        arena.allocate(50000);
+        assert_eq!(arena.memory_usage(), 64 * 1024 + 50000 + 2 * size_of::<Block>());
+    }
+
+    #[test]
+    fn test_arena_geometric_growth() {
+        let mut arena = Arena::with_options(ArenaOptions {
+            block_size: 1024,
+            growth: GrowthPolicy::Geometric { max_block_size: 8192 },
+            ..ArenaOptions::default()
+        });
+        let mut expected = 0;
+        for size in [1024, 2048, 4096, 8192, 8192] {
+            arena.allocate(200);
+            expected += size + size_of::<Block>();
+            assert_eq!(arena.memory_usage(), expected);
+            // Use up the rest of the block.
+            arena.allocate(size - 200);
+        }
+    }
+
+    #[test]
+    fn test_arena_reserve() {
+        let mut arena = Arena::new();
+        arena.reserve(100000).unwrap();
+        let usage = arena.memory_usage();
+        assert_eq!(usage, 100000 + size_of::<Block>());
+        for _ in 0..10 {
+            arena.allocate(5000);
+            arena.allocate_aligned(3);
+        }
+        assert_eq!(arena.memory_usage(), usage);
+
+        // Already enough room: nothing new is allocated.
+        arena.reserve(1000).unwrap();
+        assert_eq!(arena.memory_usage(), usage);
+
+        let mut arena = Arena::with_limit(BLOCK_SIZE);
+        assert!(matches!(arena.reserve(BLOCK_SIZE), Err(Error::ArenaFull)));
+    }
+
+    #[test]
+    fn test_arena_reset() {
+        let mut arena = Arena::new();
+        arena.allocate(100);
+        for _ in 0..10 {
+            arena.allocate(1000);
+        }
+        arena.allocate(10000);
+        let usage = arena.memory_usage();
+
+        // Every block is kept and reused in place of a new one.
+        arena.reset();
+        assert_eq!(arena.memory_usage(), usage);
+        let mut seen = 0;
+        for _ in 0..10 {
+            arena.allocate(1000);
+            seen += 1000;
+        }
+        arena.allocate(10000);
+        assert!(seen > BLOCK_SIZE);
+        assert_eq!(arena.memory_usage(), usage);
+
+        let mut arena = Arena::with_options(ArenaOptions { max_retained: Some(BLOCK_SIZE), ..ArenaOptions::default() });
+        for _ in 0..10 {
+            arena.allocate(1000);
+        }
+        arena.reset();
+        assert_eq!(arena.memory_usage(), BLOCK_SIZE + size_of::<Block>());
+        arena.allocate(1000);
+        assert_eq!(arena.memory_usage(), BLOCK_SIZE + size_of::<Block>());
+    }
+
+    #[test]
+    fn test_arena_stats() {
+        let mut arena = Arena::new();
+        arena.allocate(3);
+        arena.allocate_aligned(16);
+        arena.allocate(5000);
+        arena.allocate(10);
+        let stats = arena.stats();
+        assert_eq!(stats.allocated_bytes, 5029);
+        assert_eq!(stats.alignment_waste, 5);
+        assert_eq!((stats.blocks, stats.large_blocks), (2, 1));
+        assert_eq!(stats.block_bytes, BLOCK_SIZE + 5000);
+        assert_eq!(stats.block_bytes + stats.bookkeeping_bytes, arena.memory_usage());
+        assert_eq!(stats.unused_bytes(), BLOCK_SIZE - 34);
+
+        arena.reset();
+        let stats = arena.stats();
+        assert_eq!((stats.allocated_bytes, stats.alignment_waste, stats.blocks), (0, 0, 2));
+    }
+
+    /// Counts the bytes it has handed out and not yet taken back.
+    #[derive(Clone, Default)]
+    struct Tracking(Arc<AtomicUsize>);
+
+    unsafe impl BlockAllocator for Tracking {
+        fn allocate(&self, layout: Layout) -> *mut u8 {
+            self.0.fetch_add(layout.size(), Ordering::Relaxed);
+            Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+            self.0.fetch_sub(layout.size(), Ordering::Relaxed);
+            Global.deallocate(ptr, layout)
+        }
+    }
+
+    struct Exhausted;
+
+    unsafe impl BlockAllocator for Exhausted {
+        fn allocate(&self, _layout: Layout) -> *mut u8 {
+            std::ptr::null_mut()
+        }
+
+        unsafe fn deallocate(&self, _ptr: *mut u8, _layout: Layout) {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn test_arena_custom_allocator() {
+        let tracking = Tracking::default();
+        let mut arena = Arena::new_in(tracking.clone());
+        for size in [10, 2000, 100, 5000, 3000, 20] {
+            arena.allocate(size);
+        }
+        // Every block, the large ones included, comes from the allocator.
+        assert_eq!(arena.memory_usage(), tracking.0.load(Ordering::Relaxed) + arena.blocks.len() * size_of::<Block>());
+
+        arena.reset();
+        arena.allocate(100);
+        drop(arena);
+        assert_eq!(tracking.0.load(Ordering::Relaxed), 0);
+
+        let mut arena = Arena::new_in(Exhausted);
+        assert!(matches!(arena.try_allocate(10), Err(Error::ArenaFull)));
+        assert_eq!(arena.memory_usage(), 0);
+    }
+
+    #[test]
+    fn test_arena_layout_alignment() {
+        let mut arena = Arena::new();
+        let mut ranges = Vec::new();
+        for (i, align) in [64, 8, 128, 4096, 16, 64].into_iter().cycle().take(60).enumerate() {
+            // Mix sizes that fit the current block with ones that need a new
+            // block or a block of their own.
+            let size = [3, 200, 2000, 40][i % 4];
+            let p = arena.try_allocate_layout(Layout::from_size_align(size, align).unwrap()).unwrap();
+            assert_eq!(p as usize % align, 0, "size {} align {}", size, align);
+            unsafe { std::ptr::write_bytes(p, i as u8, size) };
+            ranges.push((p as usize, size, i as u8));
+        }
+        for &(p, size, fill) in &ranges {
+            let bytes = unsafe { std::slice::from_raw_parts(p as *const u8, size) };
+            assert!(bytes.iter().all(|&b| b == fill));
+        }
+        let stats = arena.stats();
+        assert_eq!(stats.allocated_bytes, ranges.iter().map(|r| r.1).sum::<usize>());
+    }
+
+    #[test]
+    #[should_panic(expected = "arena memory limit reached")]
+    fn test_arena_limit_panics() {
+        let mut arena = Arena::with_limit(BLOCK_SIZE);
+        arena.allocate(BLOCK_SIZE);
+    }
+}
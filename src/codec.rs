@@ -0,0 +1,75 @@
+/// Converts keys to and from bytes for the on-disk formats.
+///
+/// `decode` receives exactly the bytes produced by `encode` and returns
+/// `None` if they do not form a valid key.
+pub trait KeyCodec: Sized {
+    fn encode(&self, dst: &mut Vec<u8>);
+
+    fn decode(src: &[u8]) -> Option<Self>;
+}
+
+macro_rules! int_codec {
+    ($($t:ty),*) => {$(
+        // Big-endian so that neighbouring keys share leading bytes.
+        impl KeyCodec for $t {
+            fn encode(&self, dst: &mut Vec<u8>) {
+                dst.extend_from_slice(&self.to_be_bytes());
+            }
+
+            fn decode(src: &[u8]) -> Option<Self> {
+                Some(<$t>::from_be_bytes(src.try_into().ok()?))
+            }
+        }
+    )*};
+}
+
+int_codec!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl KeyCodec for Vec<u8> {
+    fn encode(&self, dst: &mut Vec<u8>) {
+        dst.extend_from_slice(self);
+    }
+
+    fn decode(src: &[u8]) -> Option<Self> {
+        Some(src.to_vec())
+    }
+}
+
+impl KeyCodec for String {
+    fn encode(&self, dst: &mut Vec<u8>) {
+        dst.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(src: &[u8]) -> Option<Self> {
+        String::from_utf8(src.to_vec()).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::KeyCodec;
+
+    fn round_trip<K: KeyCodec + PartialEq + std::fmt::Debug>(key: K) {
+        let mut buf = Vec::new();
+        key.encode(&mut buf);
+        assert_eq!(K::decode(&buf), Some(key));
+    }
+
+    #[test]
+    fn round_trips() {
+        round_trip(0u8);
+        round_trip(u64::MAX);
+        round_trip(-1i32);
+        round_trip(i128::MIN);
+        round_trip(Vec::<u8>::new());
+        round_trip(b"\x00\xffkey".to_vec());
+        round_trip(String::from("key"));
+    }
+
+    #[test]
+    fn invalid_input() {
+        assert_eq!(u32::decode(&[1, 2, 3]), None);
+        assert_eq!(u16::decode(&[1, 2, 3]), None);
+        assert_eq!(String::decode(&[0xff, 0xfe]), None);
+    }
+}
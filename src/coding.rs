@@ -0,0 +1,157 @@
+//! Little-endian fixed-width and varint encodings, as in LevelDB's `util/coding`.
+
+pub fn put_fixed32(dst: &mut Vec<u8>, value: u32) {
+    dst.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn put_fixed64(dst: &mut Vec<u8>, value: u64) {
+    dst.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn decode_fixed32(src: &[u8]) -> u32 {
+    u32::from_le_bytes(src[..4].try_into().unwrap())
+}
+
+pub fn decode_fixed64(src: &[u8]) -> u64 {
+    u64::from_le_bytes(src[..8].try_into().unwrap())
+}
+
+pub fn put_varint32(dst: &mut Vec<u8>, value: u32) {
+    put_varint64(dst, value as u64)
+}
+
+pub fn put_varint64(dst: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        dst.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    dst.push(value as u8);
+}
+
+/// Decodes a varint from the front of `src` and advances it past the value.
+pub fn get_varint32(src: &mut &[u8]) -> Option<u32> {
+    let mut result = 0u32;
+    for (i, &byte) in src.iter().enumerate().take(5) {
+        result |= ((byte & 0x7f) as u32) << (7 * i);
+        if byte & 0x80 == 0 {
+            *src = &src[i + 1..];
+            return Some(result);
+        }
+    }
+    None
+}
+
+/// Decodes a varint from the front of `src` and advances it past the value.
+pub fn get_varint64(src: &mut &[u8]) -> Option<u64> {
+    let mut result = 0u64;
+    for (i, &byte) in src.iter().enumerate().take(10) {
+        result |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            *src = &src[i + 1..];
+            return Some(result);
+        }
+    }
+    None
+}
+
+/// Decodes a varint32 length prefix and the slice it describes.
+pub fn get_length_prefixed_slice<'a>(src: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = get_varint32(src)? as usize;
+    if src.len() < len {
+        return None;
+    }
+    let (result, rest) = src.split_at(len);
+    *src = rest;
+    Some(result)
+}
+
+pub fn put_length_prefixed_slice(dst: &mut Vec<u8>, value: &[u8]) {
+    put_varint32(dst, value.len() as u32);
+    dst.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed() {
+        let mut dst = Vec::new();
+        for v in 0..100000u32 {
+            put_fixed32(&mut dst, v);
+        }
+        for (i, chunk) in dst.chunks(4).enumerate() {
+            assert_eq!(decode_fixed32(chunk), i as u32);
+        }
+
+        let mut dst = Vec::new();
+        for power in 0..64 {
+            let v = 1u64 << power;
+            put_fixed64(&mut dst, v - 1);
+            put_fixed64(&mut dst, v);
+            put_fixed64(&mut dst, v + 1);
+        }
+        let mut values = dst.chunks(8).map(decode_fixed64);
+        for power in 0..64 {
+            let v = 1u64 << power;
+            assert_eq!(values.next(), Some(v - 1));
+            assert_eq!(values.next(), Some(v));
+            assert_eq!(values.next(), Some(v + 1));
+        }
+    }
+
+    #[test]
+    fn varint() {
+        let mut values = vec![0u64, 100, u32::MAX as u64, u64::MAX, u64::MAX - 1];
+        for k in 0..64 {
+            let power = 1u64 << k;
+            values.extend([power, power - 1, power + 1]);
+        }
+
+        let mut dst = Vec::new();
+        for &v in &values {
+            put_varint64(&mut dst, v);
+        }
+        let mut src = &dst[..];
+        for &v in &values {
+            assert_eq!(get_varint64(&mut src), Some(v));
+        }
+        assert!(src.is_empty());
+
+        let mut dst = Vec::new();
+        for i in 0..32 * 32 {
+            put_varint32(&mut dst, (i / 32) << (i % 32));
+        }
+        let mut src = &dst[..];
+        for i in 0..32 * 32 {
+            assert_eq!(get_varint32(&mut src), Some((i / 32) << (i % 32)));
+        }
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn varint_truncated() {
+        let mut dst = Vec::new();
+        put_varint64(&mut dst, u64::MAX);
+        for len in 0..dst.len() {
+            assert_eq!(get_varint64(&mut &dst[..len]), None);
+        }
+        assert_eq!(get_varint32(&mut &[0x81, 0x82, 0x83, 0x84, 0x85, 0x11][..]), None);
+    }
+
+    #[test]
+    fn length_prefixed_slice() {
+        let mut dst = Vec::new();
+        put_length_prefixed_slice(&mut dst, b"");
+        put_length_prefixed_slice(&mut dst, b"foo");
+        put_length_prefixed_slice(&mut dst, &[b'x'; 200]);
+
+        let mut src = &dst[..];
+        assert_eq!(get_length_prefixed_slice(&mut src), Some(&b""[..]));
+        assert_eq!(get_length_prefixed_slice(&mut src), Some(&b"foo"[..]));
+        assert_eq!(get_length_prefixed_slice(&mut src), Some(&[b'x'; 200][..]));
+        assert!(src.is_empty());
+        assert_eq!(get_length_prefixed_slice(&mut &dst[..3]), Some(&b""[..]));
+        assert_eq!(get_length_prefixed_slice(&mut &dst[1..3]), None);
+    }
+}
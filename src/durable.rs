@@ -0,0 +1,305 @@
+use std::borrow::Borrow;
+use std::fmt::Debug;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::arena::Arena;
+use crate::codec::KeyCodec;
+use crate::error::{Error, Result};
+use crate::log;
+use crate::{SkipList, SkipListIterator};
+
+/// When `DurableSkipList::insert` forces its log record to stable storage.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// `fsync` after every insert.
+    Always,
+    /// `fsync` after every `n` inserts. Up to `n - 1` inserts can be lost when
+    /// the machine crashes, but not when only the process does.
+    Every(u32),
+    /// Leave the data in the OS page cache until `sync` is called.
+    Never,
+}
+
+struct LogState {
+    writer: log::Writer<BufWriter<File>>,
+    // Length of the log up to the end of the last complete record.
+    offset: u64,
+    unsynced: u32,
+    // Set when a failed write could not be rolled back.
+    poisoned: bool,
+}
+
+impl LogState {
+    fn append(&mut self, record: &[u8]) -> Result<()> {
+        if self.poisoned {
+            return Err(Error::Io(io::Error::new(io::ErrorKind::Other, "log is unusable after a failed write")));
+        }
+        let result = self.writer.add_record(record).and_then(|_| self.writer.flush());
+        if let Err(e) = result {
+            if self.roll_back().is_err() {
+                self.poisoned = true;
+            }
+            return Err(Error::Io(e));
+        }
+        self.offset = self.file().stream_position()?;
+        Ok(())
+    }
+
+    /// Cuts a partly written record off the log, so that later records are
+    /// not appended after it.
+    fn roll_back(&mut self) -> io::Result<()> {
+        let mut file = self.file().try_clone()?;
+        file.set_len(self.offset)?;
+        file.seek(SeekFrom::Start(self.offset))?;
+        let writer = log::Writer::with_offset(BufWriter::new(file), self.offset);
+        // Drop the old writer without flushing what is left in its buffer.
+        let (_, _) = std::mem::replace(&mut self.writer, writer).into_inner().into_parts();
+        Ok(())
+    }
+
+    fn file(&self) -> &File {
+        self.writer.get_ref().get_ref()
+    }
+}
+
+/// A skip list that appends every insert to a write-ahead log before linking
+/// it in memory, so that `recover` can rebuild the list after a crash.
+pub struct DurableSkipList<K: Ord + Debug + Default + KeyCodec> {
+    list: SkipList<K>,
+    log: Mutex<LogState>,
+    policy: SyncPolicy,
+}
+
+impl<K: Ord + Debug + Default + KeyCodec> DurableSkipList<K> {
+    /// Creates an empty list logging to `path`, replacing any existing file.
+    pub fn create<P: AsRef<Path>>(path: P, arena: Arena, policy: SyncPolicy) -> Result<Self> {
+        let list = SkipList::try_new(arena)?;
+        let file = File::create(path)?;
+        Ok(Self::with_log(list, log::Writer::new(BufWriter::new(file)), 0, policy))
+    }
+
+    /// Replays the log at `path` into a new list and keeps appending to it.
+    ///
+    /// A record torn by a crash at the end of the log is discarded and cut
+    /// off the file; corruption anywhere else, including a key logged twice,
+    /// is returned as an error.
+    pub fn recover<P: AsRef<Path>>(path: P, arena: Arena, policy: SyncPolicy) -> Result<Self> {
+        let path = path.as_ref();
+        let list = SkipList::try_new(arena)?;
+        let mut reader = log::Reader::new(BufReader::new(File::open(path)?));
+        while let Some(record) = reader.read_record()? {
+            let key = K::decode(&record)
+                .ok_or_else(|| Error::Corruption("bad key in log".into()))?;
+            if list.contains(&key) {
+                return Err(Error::Corruption(format!("key {:?} is logged twice", key)));
+            }
+            list.try_insert(key)?;
+        }
+
+        let end = reader.last_record_end();
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        file.set_len(end)?;
+        file.sync_data()?;
+        file.seek(SeekFrom::Start(end))?;
+        Ok(Self::with_log(list, log::Writer::with_offset(BufWriter::new(file), end), end, policy))
+    }
+
+    fn with_log(list: SkipList<K>, writer: log::Writer<BufWriter<File>>, offset: u64, policy: SyncPolicy) -> Self {
+        let log = LogState { writer, offset, unsynced: 0, poisoned: false };
+        DurableSkipList { list, log: Mutex::new(log), policy }
+    }
+
+    /// Logs `key` and then inserts it into the list.
+    ///
+    /// Returns `Error::KeyExists` without logging anything if the key is
+    /// already present.
+    ///
+    /// If writing the record fails, or the arena is full, the record is cut
+    /// off the log again and the key is not inserted. If only the sync that
+    /// follows fails, the key is inserted anyway, since it is in the log, and
+    /// the error reports that it may not be durable yet.
+    pub fn insert(&self, key: K) -> Result<()> {
+        let mut log = self.log.lock().unwrap();
+        if self.list.contains(&key) {
+            return Err(Error::KeyExists);
+        }
+
+        let mut record = Vec::new();
+        key.encode(&mut record);
+        let start = log.offset;
+        log.append(&record)?;
+        if let Err(e) = self.list.try_insert(key) {
+            log.offset = start;
+            if log.roll_back().is_err() {
+                log.poisoned = true;
+            }
+            return Err(e);
+        }
+
+        log.unsynced += 1;
+        let sync = match self.policy {
+            SyncPolicy::Always => true,
+            SyncPolicy::Every(n) => log.unsynced >= n,
+            SyncPolicy::Never => false,
+        };
+        if sync {
+            log.file().sync_data()?;
+            log.unsynced = 0;
+        }
+        Ok(())
+    }
+
+    /// Forces every logged insert to stable storage.
+    pub fn sync(&self) -> Result<()> {
+        let mut log = self.log.lock().unwrap();
+        log.writer.flush()?;
+        log.file().sync_data()?;
+        log.unsynced = 0;
+        Ok(())
+    }
+
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.list.contains(key)
+    }
+
+    pub fn iter(&self) -> SkipListIterator<K> {
+        self.list.iter()
+    }
+
+    /// The in-memory list, for reads.
+    pub fn list(&self) -> &SkipList<K> {
+        &self.list
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, OpenOptions};
+    use crate::arena::Arena;
+    use crate::error::Error;
+    use crate::test_util::{keys, TempPath};
+    use super::{DurableSkipList, SyncPolicy};
+
+    #[test]
+    fn recover() {
+        let path = TempPath::new("recover");
+        let mut expected = Vec::new();
+        {
+            let list = DurableSkipList::create(&path.0, Arena::new(), SyncPolicy::Every(10)).unwrap();
+            for i in (0..1000).rev() {
+                list.insert(format!("key{:04}", i)).unwrap();
+                expected.push(format!("key{:04}", i));
+            }
+            // Keys that span several log blocks.
+            list.insert("x".repeat(100000)).unwrap();
+            expected.push("x".repeat(100000));
+        }
+        expected.sort();
+
+        let list = DurableSkipList::<String>::recover(&path.0, Arena::new(), SyncPolicy::Always).unwrap();
+        assert_eq!(keys(list.iter()), expected);
+
+        // Inserts after recovery are appended to the same log.
+        list.insert("zzz".to_string()).unwrap();
+        drop(list);
+        expected.push("zzz".to_string());
+        let list = DurableSkipList::<String>::recover(&path.0, Arena::new(), SyncPolicy::Never).unwrap();
+        assert_eq!(keys(list.iter()), expected);
+    }
+
+    #[test]
+    fn torn_final_record() {
+        let path = TempPath::new("torn");
+        {
+            let list = DurableSkipList::create(&path.0, Arena::new(), SyncPolicy::Never).unwrap();
+            for k in ["a", "b", "c"] {
+                list.insert(k.to_string()).unwrap();
+            }
+            list.sync().unwrap();
+        }
+        let len = fs::metadata(&path.0).unwrap().len();
+        OpenOptions::new().write(true).open(&path.0).unwrap().set_len(len - 1).unwrap();
+
+        let list = DurableSkipList::<String>::recover(&path.0, Arena::new(), SyncPolicy::Always).unwrap();
+        assert_eq!(keys(list.iter()), ["a", "b"]);
+        list.insert("d".to_string()).unwrap();
+        drop(list);
+
+        let list = DurableSkipList::<String>::recover(&path.0, Arena::new(), SyncPolicy::Always).unwrap();
+        assert_eq!(keys(list.iter()), ["a", "b", "d"]);
+    }
+
+    #[test]
+    fn duplicate_and_full() {
+        let path = TempPath::new("duplicate");
+        let list = DurableSkipList::create(&path.0, Arena::with_limit(16 * 1024), SyncPolicy::Never).unwrap();
+        list.insert("a".to_string()).unwrap();
+        assert!(matches!(list.insert("a".to_string()), Err(Error::KeyExists)));
+        list.insert("b".to_string()).unwrap();
+
+        // A key the arena cannot hold is taken back out of the log.
+        let mut inserted = vec!["a".to_string(), "b".to_string()];
+        for i in 0.. {
+            match list.insert(format!("k{:05}", i)) {
+                Ok(()) => inserted.push(format!("k{:05}", i)),
+                Err(Error::ArenaFull) => break,
+                Err(e) => panic!("{}", e),
+            }
+        }
+        assert!(matches!(list.insert("a".to_string()), Err(Error::KeyExists)));
+        list.sync().unwrap();
+        drop(list);
+
+        let list = DurableSkipList::<String>::recover(&path.0, Arena::new(), SyncPolicy::Always).unwrap();
+        assert_eq!(keys(list.iter()), inserted);
+        list.insert("c".to_string()).unwrap();
+        drop(list);
+
+        // An arena too small for the head or for the logged keys.
+        let result = DurableSkipList::<String>::create(&path.0, Arena::with_limit(64), SyncPolicy::Never);
+        assert!(matches!(result, Err(Error::ArenaFull)));
+        let result = DurableSkipList::<String>::recover(&path.0, Arena::with_limit(64), SyncPolicy::Never);
+        assert!(matches!(result, Err(Error::ArenaFull)));
+        let result = DurableSkipList::<String>::recover(&path.0, Arena::with_limit(16 * 1024), SyncPolicy::Never);
+        assert!(matches!(result, Err(Error::ArenaFull)));
+    }
+
+    #[test]
+    fn corrupted_log() {
+        let path = TempPath::new("corrupt");
+        {
+            let list = DurableSkipList::create(&path.0, Arena::new(), SyncPolicy::Always).unwrap();
+            for k in ["a", "b", "c"] {
+                list.insert(k.to_string()).unwrap();
+            }
+        }
+        let mut data = fs::read(&path.0).unwrap();
+        data[crate::log::HEADER_SIZE] ^= 1;
+        fs::write(&path.0, data).unwrap();
+
+        let result = DurableSkipList::<String>::recover(&path.0, Arena::new(), SyncPolicy::Always);
+        assert!(matches!(result, Err(Error::Corruption(_))));
+
+        // A key logged twice.
+        {
+            let list = DurableSkipList::create(&path.0, Arena::new(), SyncPolicy::Always).unwrap();
+            list.insert("a".to_string()).unwrap();
+        }
+        let mut data = fs::read(&path.0).unwrap();
+        data.extend_from_within(..);
+        fs::write(&path.0, data).unwrap();
+        let result = DurableSkipList::<String>::recover(&path.0, Arena::new(), SyncPolicy::Always);
+        assert!(matches!(result, Err(Error::Corruption(_))));
+
+        let missing = TempPath::new("missing");
+        let result = DurableSkipList::<String>::recover(&missing.0, Arena::new(), SyncPolicy::Always);
+        assert!(matches!(result, Err(Error::Io(_))));
+    }
+}
@@ -0,0 +1,43 @@
+use std::{fmt, io};
+
+#[derive(Debug)]
+pub enum Error {
+    /// A key passed to a sorted bulk load was not greater than the key before it.
+    OutOfOrder,
+    /// The arena could not supply more memory without exceeding its limit.
+    ArenaFull,
+    /// The key is already in the list.
+    KeyExists,
+    /// Persisted data failed validation.
+    Corruption(String),
+    Io(io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::OutOfOrder => write!(f, "keys are not in strictly increasing order"),
+            Error::ArenaFull => write!(f, "arena memory limit reached"),
+            Error::KeyExists => write!(f, "key is already in the list"),
+            Error::Corruption(msg) => write!(f, "corruption: {}", msg),
+            Error::Io(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
@@ -0,0 +1,146 @@
+use std::borrow::Borrow;
+use std::ops::{Bound, RangeBounds};
+
+/// An immutable snapshot of a skip list, produced by `SkipList::freeze`.
+///
+/// The keys are compacted into one sorted array, so lookups are binary
+/// searches without atomic loads and the value can be shared between threads
+/// without any locking.
+pub struct FrozenSkipList<K> {
+    keys: Vec<K>,
+}
+
+impl<K: Ord> FrozenSkipList<K> {
+    pub(crate) fn new(keys: Vec<K>) -> Self {
+        debug_assert!(keys.windows(2).all(|w| w[0] < w[1]));
+        FrozenSkipList { keys }
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn first(&self) -> Option<&K> {
+        self.keys.first()
+    }
+
+    pub fn last(&self) -> Option<&K> {
+        self.keys.last()
+    }
+
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.keys.binary_search_by(|k| k.borrow().cmp(key)).is_ok()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, K> {
+        self.keys.iter()
+    }
+
+    /// Returns the keys within `range`, in order.
+    pub fn range<Q, R>(&self, range: R) -> std::slice::Iter<'_, K>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+        R: RangeBounds<Q>,
+    {
+        let start = match range.start_bound() {
+            Bound::Included(q) => self.keys.partition_point(|k| k.borrow() < q),
+            Bound::Excluded(q) => self.keys.partition_point(|k| k.borrow() <= q),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(q) => self.keys.partition_point(|k| k.borrow() <= q),
+            Bound::Excluded(q) => self.keys.partition_point(|k| k.borrow() < q),
+            Bound::Unbounded => self.keys.len(),
+        };
+        self.keys[start..end.max(start)].iter()
+    }
+}
+
+impl<'a, K: Ord> IntoIterator for &'a FrozenSkipList<K> {
+    type Item = &'a K;
+    type IntoIter = std::slice::Iter<'a, K>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::ops::Bound;
+    use std::thread;
+    use rand::prelude::StdRng;
+    use rand::{Rng, SeedableRng};
+    use crate::arena::Arena;
+    use crate::SkipList;
+
+    #[test]
+    fn test_empty() {
+        let frozen = SkipList::<u64>::new(Arena::new()).freeze();
+        assert!(frozen.is_empty());
+        assert_eq!(frozen.first(), None);
+        assert_eq!(frozen.last(), None);
+        assert!(!frozen.contains(&1));
+        assert_eq!(frozen.range(0..10).count(), 0);
+    }
+
+    #[test]
+    fn freeze_matches_model() {
+        let mut rng = StdRng::seed_from_u64(301);
+        let list = SkipList::new(Arena::new());
+        let mut keys = BTreeSet::new();
+        for _ in 0..2000 {
+            let key = rng.gen_range(0..5000u64);
+            if keys.insert(key) {
+                list.insert(key);
+            }
+        }
+
+        let frozen = list.freeze();
+        assert_eq!(frozen.len(), keys.len());
+        assert_eq!(frozen.first(), keys.first());
+        assert_eq!(frozen.last(), keys.last());
+        assert!(frozen.iter().eq(keys.iter()));
+
+        for _ in 0..1000 {
+            let a = rng.gen_range(0..5100);
+            let b = rng.gen_range(a..5100);
+            assert!(frozen.range(a..b).eq(keys.range(a..b)));
+            assert!(frozen.range(a..=b).eq(keys.range(a..=b)));
+            assert!(frozen.range(a..).eq(keys.range(a..)));
+            assert!(frozen.range(..b).eq(keys.range(..b)));
+            let bounds = (Bound::Excluded(a), Bound::Included(b));
+            assert!(frozen.range(bounds).eq(keys.range(bounds)));
+            assert_eq!(frozen.contains(&a), keys.contains(&a));
+        }
+    }
+
+    #[test]
+    fn shared_between_threads() {
+        let list = SkipList::new(Arena::new());
+        for s in ["a", "b", "c"] {
+            list.insert(s.to_string());
+        }
+        let frozen = list.freeze();
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    assert!(frozen.contains("b"));
+                    let keys: Vec<_> = frozen.range::<str, _>((Bound::Included("b"), Bound::Unbounded)).collect();
+                    assert_eq!(keys, ["b", "c"]);
+                });
+            }
+        });
+    }
+}
@@ -0,0 +1,208 @@
+//! Arena blocks backed by huge pages, optionally placed on one NUMA node.
+//!
+//! A traversal of a large list touches nodes spread over many blocks, and
+//! with 4 KiB pages nearly every hop can miss the TLB. Mapping blocks with
+//! 2 MiB pages covers the same memory with 512 times fewer TLB entries.
+//!
+//! Use it with block sizes that are multiples of `HUGE_PAGE_SIZE`:
+//!
+//! ```
+//! use skiplist_rust::arena::{Arena, ArenaOptions};
+//! use skiplist_rust::hugepage::{HugePageAllocator, HUGE_PAGE_SIZE};
+//! use skiplist_rust::SkipList;
+//!
+//! let options = ArenaOptions { block_size: HUGE_PAGE_SIZE, ..ArenaOptions::default() };
+//! let list = SkipList::new(Arena::with_options_in(options, HugePageAllocator::new()));
+//! list.insert(42u64);
+//! ```
+//!
+//! Every step that needs support from the system degrades quietly: a failed
+//! hugetlb mapping falls back to normal pages, and `madvise` and `mbind`
+//! errors are ignored.
+
+use std::alloc::Layout;
+use std::ptr;
+
+use crate::arena::BlockAllocator;
+
+/// The huge page size on x86-64 and on most arm64 kernels.
+pub const HUGE_PAGE_SIZE: usize = 2 << 20;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HugePageOptions {
+    /// Take pages from the preallocated hugetlb pool (`vm.nr_hugepages`)
+    /// with `MAP_HUGETLB`. Otherwise blocks are ordinary mappings marked
+    /// with `MADV_HUGEPAGE` for transparent huge pages.
+    pub hugetlb: bool,
+    /// Prefer memory on this NUMA node.
+    pub numa_node: Option<u32>,
+}
+
+/// A `BlockAllocator` that maps every block separately with `mmap`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HugePageAllocator {
+    options: HugePageOptions,
+}
+
+impl HugePageAllocator {
+    /// Uses transparent huge pages on the local node.
+    pub fn new() -> Self {
+        Self::with_options(HugePageOptions::default())
+    }
+
+    pub fn with_options(options: HugePageOptions) -> Self {
+        HugePageAllocator { options }
+    }
+
+    fn mapping_len(&self, layout: Layout) -> usize {
+        // hugetlb mappings must be unmapped in whole huge pages, so use the
+        // same length whether or not the fallback was taken.
+        let unit = if self.options.hugetlb { HUGE_PAGE_SIZE } else { page_size() };
+        (layout.size() + unit - 1) & !(unit - 1)
+    }
+}
+
+unsafe impl BlockAllocator for HugePageAllocator {
+    fn allocate(&self, layout: Layout) -> *mut u8 {
+        let len = self.mapping_len(layout);
+        if layout.align() > page_size() || len == 0 {
+            return ptr::null_mut();
+        }
+
+        let flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
+        let prot = libc::PROT_READ | libc::PROT_WRITE;
+        let mut addr = libc::MAP_FAILED;
+        if self.options.hugetlb {
+            addr = unsafe { libc::mmap(ptr::null_mut(), len, prot, flags | libc::MAP_HUGETLB, -1, 0) };
+        }
+        if addr == libc::MAP_FAILED {
+            addr = map_huge_page_aligned(len, prot, flags);
+            if addr == libc::MAP_FAILED {
+                return ptr::null_mut();
+            }
+            unsafe {
+                libc::madvise(addr, len, libc::MADV_HUGEPAGE);
+            }
+        }
+
+        // Set the policy before the pages are first touched, which is when
+        // they are placed.
+        if let Some(node) = self.options.numa_node {
+            bind_to_node(addr, len, node);
+        }
+        addr.cast()
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+        // Both paths leave exactly `mapping_len` bytes mapped at `ptr`.
+        libc::munmap(ptr.cast(), self.mapping_len(layout));
+    }
+}
+
+/// Maps `len` bytes starting on a huge page boundary. The kernel only backs
+/// whole, aligned 2 MiB ranges with transparent huge pages, and a plain
+/// `mmap` is only page-aligned, so this maps an extra huge page and unmaps
+/// the slack on either side.
+fn map_huge_page_aligned(len: usize, prot: libc::c_int, flags: libc::c_int) -> *mut libc::c_void {
+    if len < HUGE_PAGE_SIZE {
+        // Too small to hold a huge page wherever it starts.
+        return unsafe { libc::mmap(ptr::null_mut(), len, prot, flags, -1, 0) };
+    }
+    let addr = unsafe { libc::mmap(ptr::null_mut(), len + HUGE_PAGE_SIZE, prot, flags, -1, 0) };
+    if addr == libc::MAP_FAILED {
+        return addr;
+    }
+    let head = (addr as usize).wrapping_neg() & (HUGE_PAGE_SIZE - 1);
+    let tail = HUGE_PAGE_SIZE - head;
+    unsafe {
+        if head > 0 {
+            libc::munmap(addr, head);
+        }
+        libc::munmap(addr.cast::<u8>().add(head + len).cast(), tail);
+        addr.cast::<u8>().add(head).cast()
+    }
+}
+
+fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+fn bind_to_node(addr: *mut libc::c_void, len: usize, node: u32) {
+    let bits = u64::BITS as usize;
+    let mut mask = vec![0u64; node as usize / bits + 1];
+    mask[node as usize / bits] |= 1 << (node as usize % bits);
+    // MPOL_PREFERRED rather than MPOL_BIND, so that a full or missing node
+    // falls back to the others instead of failing allocations.
+    unsafe {
+        libc::syscall(libc::SYS_mbind, addr, len, libc::MPOL_PREFERRED, mask.as_ptr(), mask.len() * bits + 1, 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::alloc::Layout;
+    use crate::arena::{Arena, ArenaOptions, BlockAllocator};
+    use crate::SkipList;
+    use super::{HugePageAllocator, HugePageOptions, HUGE_PAGE_SIZE};
+
+    fn fill(mut arena: Arena<HugePageAllocator>) {
+        let mut blocks = Vec::new();
+        for i in 0..64 {
+            let p = arena.allocate(100000);
+            unsafe { std::ptr::write_bytes(p, i as u8, 100000) };
+            blocks.push(p);
+        }
+        for (i, &p) in blocks.iter().enumerate() {
+            let bytes = unsafe { std::slice::from_raw_parts(p, 100000) };
+            assert!(bytes.iter().all(|&b| b == i as u8));
+        }
+    }
+
+    #[test]
+    fn transparent_huge_pages() {
+        let options = ArenaOptions { block_size: HUGE_PAGE_SIZE, ..ArenaOptions::default() };
+        fill(Arena::with_options_in(options, HugePageAllocator::new()));
+
+        let list = SkipList::new(Arena::with_options_in(options, HugePageAllocator::new()));
+        for i in (0..10000u64).rev() {
+            list.insert(i);
+        }
+        let mut iter = list.iter();
+        iter.seek_to_first();
+        for i in 0..10000u64 {
+            assert_eq!(*iter.key(), i);
+            iter.next();
+        }
+    }
+
+    #[test]
+    fn blocks_start_on_huge_pages() {
+        let allocator = HugePageAllocator::new();
+        let mut blocks = Vec::new();
+        for size in [HUGE_PAGE_SIZE, 3 * HUGE_PAGE_SIZE, HUGE_PAGE_SIZE + 4096] {
+            let layout = Layout::from_size_align(size, 8).unwrap();
+            let p = allocator.allocate(layout);
+            assert!(!p.is_null());
+            assert_eq!(p as usize % HUGE_PAGE_SIZE, 0);
+            unsafe { std::ptr::write_bytes(p, 0xab, size) };
+            blocks.push((p, layout));
+        }
+        for (p, layout) in blocks {
+            unsafe { allocator.deallocate(p, layout) };
+        }
+    }
+
+    #[test]
+    fn fallbacks() {
+        // Without a hugetlb pool and with a node that does not exist, blocks
+        // still come from normal pages.
+        for options in [
+            HugePageOptions { hugetlb: true, numa_node: None },
+            HugePageOptions { hugetlb: false, numa_node: Some(0) },
+            HugePageOptions { hugetlb: true, numa_node: Some(1000) },
+        ] {
+            let arena_options = ArenaOptions { block_size: HUGE_PAGE_SIZE, ..ArenaOptions::default() };
+            fill(Arena::with_options_in(arena_options, HugePageAllocator::with_options(options)));
+        }
+    }
+}
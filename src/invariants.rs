@@ -0,0 +1,196 @@
+//! Structural checks for tests and, with the `debug-checks` feature, for the
+//! list's own mutations.
+
+use std::fmt::Debug;
+
+use crate::arena::BlockAllocator;
+use crate::error::{Error, Result};
+use crate::{Node, SkipListImpl, MAX_HEIGHT};
+
+impl<K: Ord + Debug + Default, A: BlockAllocator> SkipListImpl<K, A> {
+    /// Verifies that the list is well-formed:
+    ///
+    /// - every level is strictly increasing,
+    /// - every level is a subsequence of the level below it,
+    /// - no tower is taller than `max_height` and every node is linked only
+    ///   at levels its tower has,
+    /// - the head has a full tower and no links at or above `max_height`,
+    /// - level 0 holds `len` nodes.
+    ///
+    /// Takes O(n * max_height) and must not run while a writer inserts.
+    pub fn check_invariants(&self) -> Result<()> {
+        let max_height = self.get_max_height();
+        if !(1..=MAX_HEIGHT).contains(&max_height) {
+            return Err(corruption(format!("max_height is {}", max_height)));
+        }
+        let head = unsafe { self.head.as_ref() };
+        if head.next.len() != MAX_HEIGHT {
+            return Err(corruption(format!("head tower has {} levels", head.next.len())));
+        }
+        if let Some(level) = (max_height..MAX_HEIGHT).find(|&l| !head.next(l).is_null()) {
+            return Err(corruption(format!("head is linked at level {}, above max_height {}", level, max_height)));
+        }
+
+        let mut len = 0;
+        for level in 0..max_height {
+            let mut below = if level > 0 { head.next(level - 1) } else { std::ptr::null_mut() };
+            let mut prev: Option<&Node<K>> = None;
+            let mut x = head.next(level);
+            while let Some(node) = unsafe { x.as_ref() } {
+                let height = node.next.len();
+                if height <= level {
+                    return Err(corruption(format!(
+                        "{:?} is linked at level {} but its tower has {} levels", node.key, level, height)));
+                }
+                if height > max_height {
+                    return Err(corruption(format!(
+                        "{:?} has a tower of {} levels, above max_height {}", node.key, height, max_height)));
+                }
+                if let Some(prev) = prev {
+                    if prev.key >= node.key {
+                        return Err(corruption(format!(
+                            "level {} is not sorted: {:?} comes before {:?}", level, prev.key, node.key)));
+                    }
+                }
+                if level > 0 {
+                    // Both levels are sorted, so the node must turn up ahead
+                    // on the level below.
+                    while below != x {
+                        match unsafe { below.as_ref() } {
+                            Some(b) => below = b.next(level - 1),
+                            None => {
+                                return Err(corruption(format!(
+                                    "{:?} is linked at level {} but not at level {}", node.key, level, level - 1)));
+                            }
+                        }
+                    }
+                } else {
+                    len += 1;
+                }
+                prev = Some(node);
+                x = node.next(level);
+            }
+        }
+
+        if len != self.len() {
+            return Err(corruption(format!("level 0 has {} nodes but len is {}", len, self.len())));
+        }
+        Ok(())
+    }
+
+    /// Runs `check_invariants` when the `debug-checks` feature is enabled and
+    /// panics if it fails.
+    pub(crate) fn debug_check(&self) {
+        #[cfg(feature = "debug-checks")]
+        if let Err(e) = self.check_invariants() {
+            panic!("{}", e);
+        }
+    }
+
+    /// With `debug-checks`, verifies that a node just inserted sits between
+    /// its neighbours on every level. Cheaper than a full check per insert.
+    pub(crate) fn debug_check_links(&self, node: &Node<K>) {
+        #[cfg(feature = "debug-checks")]
+        for level in 0..node.next.len() {
+            let next = unsafe { node.next(level).as_ref() };
+            if let Some(next) = next.filter(|next| next.key <= node.key) {
+                panic!("{}", corruption(format!(
+                    "level {} is not sorted: {:?} comes before {:?}", level, node.key, next.key)));
+            }
+        }
+        #[cfg(not(feature = "debug-checks"))]
+        let _ = node;
+    }
+}
+
+fn corruption(msg: String) -> Error {
+    Error::Corruption(format!("skip list invariant violated: {}", msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+    use std::sync::Arc;
+    use std::thread;
+    use rand::prelude::StdRng;
+    use rand::{Rng, SeedableRng};
+    use crate::arena::Arena;
+    use crate::error::Error;
+    use crate::{SkipList, SkipListImpl};
+
+    fn list(n: u64) -> SkipListImpl<u64> {
+        let mut list = SkipListImpl::new(Arena::new());
+        let mut rng = StdRng::seed_from_u64(301);
+        for _ in 0..n {
+            let key = rng.gen_range(0..n * 10);
+            if !list.contains(&key) {
+                list.insert(key);
+            }
+        }
+        list
+    }
+
+    fn nth(list: &SkipListImpl<u64>, n: usize) -> *mut crate::Node<u64> {
+        let mut x = unsafe { list.head.as_ref().next(0) };
+        for _ in 0..n {
+            x = unsafe { (*x).next(0) };
+        }
+        x
+    }
+
+    #[test]
+    fn well_formed() {
+        let empty: SkipListImpl<u64> = SkipListImpl::new(Arena::new());
+        empty.check_invariants().unwrap();
+        list(10000).check_invariants().unwrap();
+
+        let list = Arc::new(SkipList::new(Arena::new()));
+        let writers: Vec<_> = (0..4u64).map(|t| {
+            let list = Arc::clone(&list);
+            thread::spawn(move || {
+                for i in 0..2000 {
+                    list.insert(i * 4 + t);
+                }
+            })
+        }).collect();
+        for w in writers {
+            w.join().unwrap();
+        }
+        unsafe { (*list.skip_list.get()).check_invariants().unwrap() };
+    }
+
+    #[test]
+    fn unsorted_level() {
+        let list = list(100);
+        let (a, b) = (nth(&list, 10), nth(&list, 11));
+        unsafe { ptr::swap(&mut (*a).key, &mut (*b).key) };
+        let result = list.check_invariants();
+        unsafe { ptr::swap(&mut (*a).key, &mut (*b).key) };
+        assert!(matches!(result, Err(Error::Corruption(msg)) if msg.contains("is not sorted")));
+    }
+
+    #[test]
+    fn level_not_a_subsequence() {
+        let list = list(1000);
+        // Unlink a tall node from level 0 only.
+        let mut i = 1;
+        while unsafe { (*nth(&list, i)).next.len() } < 2 {
+            i += 1;
+        }
+        let (prev, node) = (nth(&list, i - 1), nth(&list, i));
+        unsafe { (*prev).set_next(0, (*node).next(0)) };
+        let result = list.check_invariants();
+        unsafe { (*prev).set_next(0, node) };
+        assert!(matches!(result, Err(Error::Corruption(msg)) if msg.contains("but not at level 0")));
+    }
+
+    #[test]
+    fn tower_above_max_height() {
+        let list = list(1000);
+        let max_height = list.get_max_height();
+        list.max_height.store(max_height - 1, std::sync::atomic::Ordering::Relaxed);
+        let result = list.check_invariants();
+        list.max_height.store(max_height, std::sync::atomic::Ordering::Relaxed);
+        assert!(matches!(result, Err(Error::Corruption(_))));
+    }
+}
@@ -1,18 +1,53 @@
 pub mod arena;
+pub mod codec;
+pub mod coding;
+pub mod crc32c;
+pub mod durable;
+pub mod error;
+pub mod frozen;
+#[cfg(target_os = "linux")]
+pub mod hugepage;
+mod invariants;
+pub mod log;
+pub mod merge;
+#[cfg(unix)]
+pub mod mmap_arena;
+#[cfg(unix)]
+pub mod offset;
+mod persist;
+pub mod prefix;
+#[cfg(feature = "serde")]
+mod serde_impl;
+#[cfg(unix)]
+pub mod shared;
+pub mod stats;
+mod sync;
+pub mod table;
+#[cfg(test)]
+mod test_util;
+mod visualize;
 
+use std::borrow::Borrow;
 use std::cell::UnsafeCell;
 use std::fmt::Debug;
 use std::iter::Iterator;
+use std::mem::MaybeUninit;
 use std::ptr;
 use std::ptr::{null_mut, NonNull};
-use std::sync::atomic::{AtomicPtr, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 use rand::{Rng, SeedableRng};
 use rand::rngs::StdRng;
-use crate::arena::Arena;
+use crate::arena::{Arena, BlockAllocator, Global};
+use crate::error::{Error, Result};
+use crate::frozen::FrozenSkipList;
+use crate::prefix::{PrefixExtractor, PrefixIterator};
+use crate::stats::{MemoryStats, StructureStats};
+use crate::sync::{AtomicPtr, AtomicUsize, Mutex, Ordering};
 
 const MAX_HEIGHT: usize = 12;
 const K_BRANCHING: usize = 4;
+// Keys looked up by `structure_stats` to measure search paths.
+const SEARCH_PATH_SAMPLES: usize = 1000;
 
 pub struct Node<K> {
     key: K,
@@ -46,13 +81,13 @@ impl<K> Node<K> {
 }
 
 
-pub struct SkipListIterator<'a, K: Ord + Debug + Default> {
+pub struct SkipListIterator<'a, K: Ord + Debug + Default, A: BlockAllocator = Global> {
     node: *mut Node<K>,
-    list: &'a SkipListImpl<K>,
+    list: &'a SkipListImpl<K, A>,
 }
 
-impl<'a, K: Ord + Debug + Default> SkipListIterator<'a, K> {
-    pub fn new(list: &'a SkipListImpl<K>) -> Self {
+impl<'a, K: Ord + Debug + Default, A: BlockAllocator> SkipListIterator<'a, K, A> {
+    pub fn new(list: &'a SkipListImpl<K, A>) -> Self {
         SkipListIterator { node: null_mut(), list }
     }
 
@@ -60,7 +95,7 @@ impl<'a, K: Ord + Debug + Default> SkipListIterator<'a, K> {
         !self.node.is_null()
     }
 
-    pub fn key(&self) -> &K {
+    pub fn key(&self) -> &'a K {
         assert!(self.valid());
         unsafe { &self.node.as_ref().unwrap().key }
     }
@@ -78,7 +113,11 @@ impl<'a, K: Ord + Debug + Default> SkipListIterator<'a, K> {
         }
     }
 
-    pub fn seek(&mut self, target: &K) {
+    pub fn seek<Q>(&mut self, target: &Q)
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
         self.node = self.list.find_greater_or_equal(target, &mut None);
     }
 
@@ -94,29 +133,41 @@ impl<'a, K: Ord + Debug + Default> SkipListIterator<'a, K> {
     }
 }
 
-pub struct SkipListImpl<K: Ord + Debug + Default> {
+pub struct SkipListImpl<K: Ord + Debug + Default, A: BlockAllocator = Global> {
     head: NonNull<Node<K>>,
-    max_height: std::sync::atomic::AtomicUsize,
+    max_height: AtomicUsize,
+    len: AtomicUsize,
+    // Tower slots of every node, the head included.
+    links: usize,
     rnd: StdRng,
-    arena: Arena,
+    arena: Arena<A>,
+    // Where `clear` puts the new head if the arena cannot hold it.
+    spare_head: Box<MaybeUninit<Node<K>>>,
 }
 
-unsafe impl<K: Ord + Debug + Default + Send> Send for SkipListImpl<K> {}
-unsafe impl<K: Ord + Debug + Default + Sync> Sync for SkipListImpl<K> {}
+unsafe impl<K: Ord + Debug + Default + Send, A: BlockAllocator + Send> Send for SkipListImpl<K, A> {}
+unsafe impl<K: Ord + Debug + Default + Sync, A: BlockAllocator + Sync> Sync for SkipListImpl<K, A> {}
 
-impl<K: Ord + Debug + Default> SkipListImpl<K> {
-    pub fn new(mut arena: Arena) -> SkipListImpl<K> {
-        let head = unsafe {
-            let layout = std::alloc::Layout::new::<Node<K>>();
-            let ptr = arena.allocate(layout.size()) as *mut Node<K>;
-            ptr::write(ptr, Node::new(K::default(), MAX_HEIGHT));
-            NonNull::new_unchecked(ptr)
-        };
+impl<K: Ord + Debug + Default, A: BlockAllocator> SkipListImpl<K, A> {
+    /// # Panics
+    ///
+    /// Panics if the arena cannot hold the head node.
+    pub fn new(arena: Arena<A>) -> SkipListImpl<K, A> {
+        Self::try_new(arena).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Like `new`, but returns `Error::ArenaFull` if the arena's memory limit
+    /// leaves no room for the head node.
+    pub fn try_new(mut arena: Arena<A>) -> Result<SkipListImpl<K, A>> {
+        let head = Self::try_new_head(&mut arena)?;
         let mut s = SkipListImpl {
             head,
-            max_height: std::sync::atomic::AtomicUsize::new(1),
+            max_height: AtomicUsize::new(1),
+            len: AtomicUsize::new(0),
+            links: MAX_HEIGHT,
             rnd: StdRng::seed_from_u64(0xdeadbeef),
             arena,
+            spare_head: Box::new(MaybeUninit::uninit()),
         };
 
         for i in 0..MAX_HEIGHT {
@@ -124,20 +175,36 @@ impl<K: Ord + Debug + Default> SkipListImpl<K> {
                 s.head.as_mut().set_next(i, ptr::null_mut());
             }
         }
-        s
+        Ok(s)
+    }
+
+    fn try_new_head(arena: &mut Arena<A>) -> Result<NonNull<Node<K>>> {
+        unsafe {
+            let ptr = arena.try_allocate_layout(std::alloc::Layout::new::<Node<K>>())? as *mut Node<K>;
+            ptr::write(ptr, Node::new(K::default(), MAX_HEIGHT));
+            Ok(NonNull::new_unchecked(ptr))
+        }
     }
 
     /// # Safety
     ///
     /// This function should not be called before data ready.
-    pub unsafe fn key_is_after_node(&self, key: &K, node: *const Node<K>) -> bool {
+    pub unsafe fn key_is_after_node<Q>(&self, key: &Q, node: *const Node<K>) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
         unsafe {
-            node.as_ref().map(|n| &n.key)
+            node.as_ref().map(|n| n.key.borrow())
                 .map_or(false, |node_key| node_key < key)
         }
     }
 
-    pub fn find_greater_or_equal(&self, key: &K, prev: &mut Option<&mut Vec<*mut Node<K>>>) -> *mut Node<K> {
+    pub fn find_greater_or_equal<Q>(&self, key: &Q, prev: &mut Option<&mut Vec<*mut Node<K>>>) -> *mut Node<K>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
         let mut x = self.head.as_ptr();
         let mut level = self.get_max_height() - 1;
         loop {
@@ -157,12 +224,16 @@ impl<K: Ord + Debug + Default> SkipListImpl<K> {
         }
     }
 
-    pub fn find_less_than(&self, key: &K) -> NonNull<Node<K>> {
+    pub fn find_less_than<Q>(&self, key: &Q) -> NonNull<Node<K>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
         let mut x = self.head;
         let mut level = self.get_max_height() - 1;
         loop {
             let next = unsafe { x.as_ref().next(level) };
-            if next.is_null() || unsafe { next.as_ref().unwrap().key >= *key } {
+            if next.is_null() || unsafe { next.as_ref().unwrap().key.borrow() >= key } {
                 if level == 0 {
                     return x;
                 } else {
@@ -174,6 +245,26 @@ impl<K: Ord + Debug + Default> SkipListImpl<K> {
         }
     }
 
+    pub fn prefix_iter<'a, E>(&'a self, extractor: E, prefix: &'a E::Prefix) -> PrefixIterator<'a, K, E>
+    where
+        E: PrefixExtractor<K>,
+    {
+        let mut x = self.head.as_ptr();
+        let mut level = self.get_max_height() - 1;
+        loop {
+            let next = unsafe { x.as_ref().unwrap().next(level) };
+            let before = unsafe { next.as_ref() }
+                .map_or(false, |n| extractor.compare_prefix(&n.key, prefix) == std::cmp::Ordering::Less);
+            if before {
+                x = next;
+            } else if level == 0 {
+                return PrefixIterator::new(next, extractor, prefix);
+            } else {
+                level -= 1;
+            }
+        }
+    }
+
     pub fn find_last(&self) -> NonNull<Node<K>> {
         let mut x = self.head;
         let mut level = self.get_max_height() - 1;
@@ -191,12 +282,16 @@ impl<K: Ord + Debug + Default> SkipListImpl<K> {
         }
     }
 
-    pub fn contains(&self, key: &K) -> bool {
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
         let x = self.find_greater_or_equal(key, &mut None);
         let x_ref = unsafe { x.as_ref() };
         match x_ref {
             None => false,
-            Some(x_ref) => x_ref.key == *key,
+            Some(x_ref) => x_ref.key.borrow() == key,
         }
     }
 
@@ -213,12 +308,26 @@ impl<K: Ord + Debug + Default> SkipListImpl<K> {
         self.max_height.load(Ordering::Relaxed)
     }
 
+    /// # Panics
+    ///
+    /// Panics if the key is present or the arena's memory limit is reached.
     pub fn insert(&mut self, key: K) {
+        self.try_insert(key).unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    /// Inserts `key`, or returns `Error::ArenaFull` and leaves the list
+    /// unchanged if the arena cannot hold another node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key is present.
+    pub fn try_insert(&mut self, key: K) -> Result<()> {
         let mut prev = vec![ptr::null_mut(); MAX_HEIGHT];
         let x = self.find_greater_or_equal(&key, &mut Some(&mut prev));
         assert!(x.is_null() || unsafe { x.as_ref().unwrap().key != key });
 
         let height = self.random_height();
+        let new_node = unsafe { &mut *self.new_node(key, height)? };
         if height > self.get_max_height() {
             let i = self.get_max_height();
             for p in prev.iter_mut().take(height).skip(i) {
@@ -227,35 +336,258 @@ impl<K: Ord + Debug + Default> SkipListImpl<K> {
             self.max_height.store(height, Ordering::Relaxed);
         }
 
-        let new_node = unsafe {
-            let layout = std::alloc::Layout::new::<Node<K>>();
-            let ptr = self.arena.allocate(layout.size()) as *mut Node<K>;
-            ptr::write(ptr, Node::new(key, height));
-            &mut *ptr
-        };
         for (i, p) in prev.iter().enumerate().take(height) {
             unsafe {
                 new_node.no_barrier_set_next(i, p.as_ref().unwrap().no_barrier_next(i));
                 p.as_ref().unwrap().set_next(i, new_node);
             }
         }
+        self.len.fetch_add(1, Ordering::Relaxed);
+        self.debug_check_links(new_node);
+        Ok(())
+    }
+
+    /// Appends keys that are strictly greater than every key already in the
+    /// list, without searching for each insertion point.
+    ///
+    /// Keys accepted before an out-of-order key is found, or before the arena
+    /// fills up, stay in the list.
+    pub fn extend_sorted<I: IntoIterator<Item = K>>(&mut self, iter: I) -> Result<()> {
+        let result = self.append_sorted(iter);
+        self.debug_check();
+        result
+    }
+
+    fn append_sorted<I: IntoIterator<Item = K>>(&mut self, iter: I) -> Result<()> {
+        // The last node of every level; levels that are still empty end at the head.
+        let mut last = [self.head.as_ptr(); MAX_HEIGHT];
+        let mut x = self.head.as_ptr();
+        for level in (0..self.get_max_height()).rev() {
+            loop {
+                let next = unsafe { x.as_ref().unwrap().next(level) };
+                if next.is_null() {
+                    break;
+                }
+                x = next;
+            }
+            last[level] = x;
+        }
+
+        for key in iter {
+            if last[0] != self.head.as_ptr() && unsafe { last[0].as_ref().unwrap().key >= key } {
+                return Err(Error::OutOfOrder);
+            }
+
+            let height = self.random_height();
+            let new_node = self.new_node(key, height)?;
+            if height > self.get_max_height() {
+                self.max_height.store(height, Ordering::Relaxed);
+            }
+
+            for (i, p) in last.iter_mut().enumerate().take(height) {
+                unsafe {
+                    p.as_ref().unwrap().set_next(i, new_node);
+                }
+                *p = new_node;
+            }
+            self.len.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    fn new_node(&mut self, key: K, height: usize) -> Result<*mut Node<K>> {
+        unsafe {
+            let ptr = self.arena.try_allocate_layout(std::alloc::Layout::new::<Node<K>>())? as *mut Node<K>;
+            ptr::write(ptr, Node::new(key, height));
+            self.links += height;
+            Ok(ptr)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Describes the shape of the list. Lock-free; while a writer inserts,
+    /// the figures may be slightly out of step with each other.
+    pub fn structure_stats(&self) -> StructureStats {
+        let max_height = self.get_max_height();
+        let mut height_histogram = vec![0; MAX_HEIGHT];
+        let mut len = 0;
+        let mut x = unsafe { self.head.as_ref().next(0) };
+        while let Some(node) = unsafe { x.as_ref() } {
+            height_histogram[node.next.len() - 1] += 1;
+            len += 1;
+            x = node.next(0);
+        }
+        let level_counts = (0..MAX_HEIGHT).map(|level| height_histogram[level..].iter().sum()).collect();
+
+        // Look up evenly spaced keys.
+        let stride = (len / SEARCH_PATH_SAMPLES).max(1);
+        let mut sampled_keys = 0;
+        let mut comparisons = 0;
+        let mut x = unsafe { self.head.as_ref().next(0) };
+        let mut i = 0;
+        while let Some(node) = unsafe { x.as_ref() } {
+            if i % stride == 0 {
+                comparisons += self.search_path_length(&node.key);
+                sampled_keys += 1;
+            }
+            i += 1;
+            x = node.next(0);
+        }
+
+        StructureStats {
+            max_height,
+            len,
+            height_histogram,
+            level_counts,
+            sampled_keys,
+            average_search_path: if sampled_keys == 0 { 0.0 } else { comparisons as f64 / sampled_keys as f64 },
+        }
+    }
+
+    /// Number of nodes `find_greater_or_equal` compares `key` with.
+    fn search_path_length(&self, key: &K) -> usize {
+        let mut x = self.head.as_ptr();
+        let mut level = self.get_max_height() - 1;
+        let mut count = 0;
+        loop {
+            let next = unsafe { x.as_ref().unwrap().next(level) };
+            if !next.is_null() {
+                count += 1;
+            }
+            if unsafe { self.key_is_after_node(key, next) } {
+                x = next;
+            } else if level == 0 {
+                return count;
+            } else {
+                level -= 1;
+            }
+        }
+    }
+
+    /// The arena's `MemoryStats` with the node and key figures filled in.
+    pub fn stats(&self) -> MemoryStats {
+        let mut stats = self.arena.stats();
+        let len = self.len();
+        let node_size = std::mem::size_of::<Node<K>>();
+        let tower_bytes = self.links * std::mem::size_of::<AtomicPtr<Node<K>>>();
+        stats.len = len;
+        stats.key_bytes = len * std::mem::size_of::<K>();
+        stats.node_bytes = (len + 1) * node_size - stats.key_bytes + tower_bytes;
+        if len > 0 {
+            let total = stats.block_bytes + stats.bookkeeping_bytes + tower_bytes;
+            stats.overhead_per_node = (total - stats.key_bytes) as f64 / len as f64;
+        }
+        stats
+    }
+
+    /// Drops every key and node and rewinds the arena, keeping its blocks
+    /// (see `Arena::reset`) for the keys inserted next.
+    ///
+    /// The new head is allocated after the reset. If the arena cannot hold
+    /// it, because no block was kept and its allocator is out of memory, the
+    /// head goes into a slot set aside when the list was created, so `clear`
+    /// never fails.
+    pub fn clear(&mut self) {
+        unsafe {
+            self.drop_nodes();
+        }
+        self.arena.reset();
+        self.head = Self::try_new_head(&mut self.arena).unwrap_or_else(|_| {
+            let spare = self.spare_head.write(Node::new(K::default(), MAX_HEIGHT));
+            NonNull::from(spare)
+        });
+        self.max_height.store(1, Ordering::Relaxed);
+        self.len.store(0, Ordering::Relaxed);
+        self.links = MAX_HEIGHT;
+        self.debug_check();
+    }
+
+    /// Drops every node, the head included. Their memory is not freed.
+    unsafe fn drop_nodes(&mut self) {
+        let mut x = self.head.as_ptr();
+        while !x.is_null() {
+            let next = (*x).next(0);
+            ptr::drop_in_place(x);
+            x = next;
+        }
+    }
+
+    /// Moves every key out of the list in order and leaves the list empty.
+    fn take_keys(&mut self) -> Vec<K> {
+        self.debug_check();
+        let mut keys = Vec::new();
+        let mut x = unsafe { self.head.as_ref().next(0) };
+        while let Some(node) = unsafe { x.as_mut() } {
+            x = node.next(0);
+            unsafe {
+                keys.push(ptr::read(&node.key));
+                // The key has moved out, so only the tower is left to free.
+                ptr::drop_in_place(&mut node.next);
+            }
+        }
+
+        // The nodes still live in the arena, but they are no longer reachable.
+        for i in 0..MAX_HEIGHT {
+            unsafe {
+                self.head.as_ref().set_next(i, ptr::null_mut());
+            }
+        }
+        self.max_height.store(1, Ordering::Relaxed);
+        self.len.store(0, Ordering::Relaxed);
+        self.links = MAX_HEIGHT;
+        keys
+    }
+}
+
+impl<K: Ord + Debug + Default, A: BlockAllocator> Drop for SkipListImpl<K, A> {
+    fn drop(&mut self) {
+        if !std::thread::panicking() {
+            self.debug_check();
+        }
+        unsafe {
+            self.drop_nodes();
+        }
     }
 }
 
-struct SkipList<K: Ord + Debug + Default> {
-    skip_list: Arc<UnsafeCell<SkipListImpl<K>>>,
+pub struct SkipList<K: Ord + Debug + Default, A: BlockAllocator = Global> {
+    skip_list: Arc<UnsafeCell<SkipListImpl<K, A>>>,
     write_lock: Mutex<()>,
 }
 
-unsafe impl<K: Ord + Debug + Default + Send + Sync> Send for SkipList<K> {}
-unsafe impl<K: Ord + Debug + Default + Send + Sync> Sync for SkipList<K> {}
+// Only the writer, under the lock, touches the allocator.
+unsafe impl<K: Ord + Debug + Default + Send + Sync, A: BlockAllocator + Send> Send for SkipList<K, A> {}
+unsafe impl<K: Ord + Debug + Default + Send + Sync, A: BlockAllocator + Send> Sync for SkipList<K, A> {}
 
-impl<K: Ord + Debug + Default> SkipList<K> {
-    pub fn new(arena: Arena) -> Self {
-        SkipList {
-            skip_list: Arc::new(UnsafeCell::new(SkipListImpl::new(arena))),
+impl<K: Ord + Debug + Default, A: BlockAllocator> SkipList<K, A> {
+    /// # Panics
+    ///
+    /// Panics if the arena cannot hold the head node.
+    pub fn new(arena: Arena<A>) -> Self {
+        Self::try_new(arena).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Like `new`, but returns `Error::ArenaFull` if the arena's memory limit
+    /// leaves no room for the head node.
+    pub fn try_new(arena: Arena<A>) -> Result<Self> {
+        Ok(SkipList {
+            skip_list: Arc::new(UnsafeCell::new(SkipListImpl::try_new(arena)?)),
             write_lock: Mutex::new(()),
-        }
+        })
+    }
+
+    /// Builds a list from keys in strictly increasing order in O(n).
+    pub fn from_sorted_iter<I: IntoIterator<Item = K>>(arena: Arena<A>, iter: I) -> Result<Self> {
+        let list = SkipList::try_new(arena)?;
+        list.extend_sorted(iter)?;
+        Ok(list)
     }
 
     pub fn insert(&self, key: K) {
@@ -265,17 +597,98 @@ impl<K: Ord + Debug + Default> SkipList<K> {
         }
     }
 
-    pub fn contains(&self, key: &K) -> bool {
+    /// Inserts `key` unless the arena's memory limit would be exceeded, in
+    /// which case `Error::ArenaFull` is returned and the key is dropped.
+    ///
+    /// The limit covers the nodes, which live in the arena, but not their
+    /// towers of links, which are `Vec`s on the heap: about
+    /// `size_of::<usize>() * 4 / 3` bytes per key on average. `stats`
+    /// reports both.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key is present. The write lock is poisoned, so callers
+    /// that cannot rule out duplicates should check `contains` first.
+    pub fn try_insert(&self, key: K) -> Result<()> {
+        let _guard = self.write_lock.lock().unwrap();
+        unsafe {
+            (*self.skip_list.get()).try_insert(key)
+        }
+    }
+
+    /// Appends keys that sort after every key in the list, see `SkipListImpl::extend_sorted`.
+    pub fn extend_sorted<I: IntoIterator<Item = K>>(&self, iter: I) -> Result<()> {
+        let _guard = self.write_lock.lock().unwrap();
+        unsafe {
+            (*self.skip_list.get()).extend_sorted(iter)
+        }
+    }
+
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
         unsafe {
             (*self.skip_list.get()).contains(key)
         }
     }
 
-    pub fn iter(&self) -> SkipListIterator<K> {
+    pub fn len(&self) -> usize {
+        unsafe {
+            (*self.skip_list.get()).len()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Memory statistics of the list and its arena. Waits for a concurrent
+    /// insert to finish.
+    pub fn stats(&self) -> MemoryStats {
+        let _guard = self.write_lock.lock().unwrap();
+        unsafe {
+            (*self.skip_list.get()).stats()
+        }
+    }
+
+    /// Height histogram, per-level node counts and average search path
+    /// length (lock-free).
+    pub fn structure_stats(&self) -> StructureStats {
+        unsafe {
+            (*self.skip_list.get()).structure_stats()
+        }
+    }
+
+    pub fn iter(&self) -> SkipListIterator<K, A> {
         unsafe {
             SkipListIterator::new(&*self.skip_list.get())
         }
     }
+
+    pub fn prefix_iter<'a, E>(&'a self, extractor: E, prefix: &'a E::Prefix) -> PrefixIterator<'a, K, E>
+    where
+        E: PrefixExtractor<K>,
+    {
+        unsafe {
+            (*self.skip_list.get()).prefix_iter(extractor, prefix)
+        }
+    }
+
+    /// Removes every key, reusing the arena's memory for later inserts, see
+    /// `SkipListImpl::clear`.
+    pub fn clear(&mut self) {
+        unsafe {
+            (*self.skip_list.get()).clear();
+        }
+    }
+
+    /// Turns the list into an immutable, freely shareable snapshot.
+    pub fn freeze(self) -> FrozenSkipList<K> {
+        let keys = unsafe { (*self.skip_list.get()).take_keys() };
+        FrozenSkipList::new(keys)
+    }
 }
 
 #[cfg(test)]
@@ -286,11 +699,13 @@ mod tests {
     use std::time::Duration;
     use rand::{random, Rng, SeedableRng};
     use crate::arena::Arena;
+    use crate::error::Error;
     use super::{SkipListImpl, SkipListIterator, SkipList};
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_empty() {
         let arena = Arena::new();
-        let list = super::SkipListImpl::new(arena);
+        let list: SkipListImpl<u64> = SkipListImpl::new(arena);
         assert_eq!(list.contains(&10), false);
 
         let mut iter = SkipListIterator::new(&list);
@@ -304,6 +719,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(unused_mut, clippy::bool_assert_comparison, clippy::manual_next_back)]
     fn insert_and_lookup() {
         let n = 2000;
         let r = 5000;
@@ -392,6 +808,272 @@ mod tests {
         }
     }
 
+    #[test]
+    fn borrowed_lookup() {
+        let list = SkipList::new(Arena::new());
+        for s in ["apple", "banana", "cherry"] {
+            list.insert(s.to_string());
+        }
+
+        assert!(list.contains("banana"));
+        assert!(!list.contains("blueberry"));
+
+        let mut iter = list.iter();
+        iter.seek("b");
+        assert!(iter.valid());
+        assert_eq!(iter.key(), "banana");
+        iter.seek("cherry");
+        assert_eq!(iter.key(), "cherry");
+        iter.seek("d");
+        assert!(!iter.valid());
+
+        let list = SkipList::new(Arena::new());
+        list.insert(b"key1".to_vec());
+        list.insert(b"key2".to_vec());
+        assert!(list.contains(&b"key2"[..]));
+        assert!(!list.contains(&b"key3"[..]));
+    }
+
+    #[test]
+    fn bulk_load() {
+        let n = 10000;
+        let list = SkipList::from_sorted_iter(Arena::new(), (0..n).map(|i| i * 2)).unwrap();
+        for i in 0..n * 2 {
+            assert_eq!(list.contains(&i), i % 2 == 0);
+        }
+
+        let mut iter = list.iter();
+        iter.seek_to_last();
+        for i in (0..n).rev() {
+            assert_eq!(*iter.key(), i * 2);
+            iter.prev();
+        }
+        assert!(!iter.valid());
+
+        // Random inserts and further appends keep working on a bulk-loaded list.
+        list.insert(1);
+        list.insert(n * 2 + 1);
+        list.extend_sorted([n * 2 + 2, n * 2 + 3]).unwrap();
+        let mut iter = list.iter();
+        iter.seek(&0);
+        assert_eq!(*iter.key(), 0);
+        iter.next();
+        assert_eq!(*iter.key(), 1);
+        iter.seek(&(n * 2));
+        assert_eq!(*iter.key(), n * 2 + 1);
+        iter.next();
+        assert_eq!(*iter.key(), n * 2 + 2);
+        iter.seek_to_last();
+        assert_eq!(*iter.key(), n * 2 + 3);
+    }
+
+    #[test]
+    fn try_insert_reports_full_arena() {
+        let list = SkipList::new(Arena::with_limit(16 * 1024));
+        let mut inserted = 0u64;
+        loop {
+            match list.try_insert(inserted) {
+                Ok(()) => inserted += 1,
+                Err(Error::ArenaFull) => break,
+                Err(e) => panic!("{}", e),
+            }
+        }
+        assert!(inserted > 100);
+
+        // The failed insert left the list as it was.
+        assert!(!list.contains(&inserted));
+        let mut iter = list.iter();
+        iter.seek_to_first();
+        for i in 0..inserted {
+            assert_eq!(*iter.key(), i);
+            iter.next();
+        }
+        assert!(!iter.valid());
+    }
+
+    #[test]
+    fn over_aligned_keys() {
+        #[repr(align(64))]
+        #[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+        struct Aligned(u64);
+
+        let list = SkipList::new(Arena::new());
+        for i in (0..1000).rev() {
+            list.insert(Aligned(i));
+        }
+        let mut iter = list.iter();
+        iter.seek_to_first();
+        for i in 0..1000 {
+            assert_eq!(iter.key(), &Aligned(i));
+            assert_eq!(iter.key() as *const Aligned as usize % 64, 0);
+            iter.next();
+        }
+        assert!(!iter.valid());
+    }
+
+    #[test]
+    fn try_new_reports_full_arena() {
+        // Too small for the first block, which would hold the head.
+        let result = SkipList::<u64>::try_new(Arena::with_limit(64));
+        assert!(matches!(result, Err(Error::ArenaFull)));
+        let result = SkipListImpl::<u64>::try_new(Arena::with_limit(64));
+        assert!(matches!(result, Err(Error::ArenaFull)));
+        let result = SkipList::from_sorted_iter(Arena::with_limit(64), [1u64, 2, 3]);
+        assert!(matches!(result, Err(Error::ArenaFull)));
+
+        let list = SkipList::try_new(Arena::with_limit(16 * 1024)).unwrap();
+        list.insert(1u64);
+        assert!(list.contains(&1));
+    }
+
+    #[test]
+    fn clear_drops_keys_and_reuses_list() {
+        static DROPS: AtomicU64 = AtomicU64::new(0);
+
+        #[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+        struct Counted(u64);
+
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let mut list = SkipList::new(Arena::new());
+        for i in 0..1000 {
+            list.insert(Counted(i));
+        }
+        let before = DROPS.load(Ordering::Relaxed);
+        list.clear();
+        // Every key plus the head's default key.
+        assert_eq!(DROPS.load(Ordering::Relaxed) - before, 1001);
+
+        let mut iter = list.iter();
+        iter.seek_to_first();
+        assert!(!iter.valid());
+        for i in (0..500).rev() {
+            list.insert(Counted(i));
+        }
+        let mut iter = list.iter();
+        iter.seek_to_first();
+        for i in 0..500 {
+            assert_eq!(iter.key().0, i);
+            iter.next();
+        }
+        assert!(!iter.valid());
+
+        let before = DROPS.load(Ordering::Relaxed);
+        drop(list);
+        assert_eq!(DROPS.load(Ordering::Relaxed) - before, 501);
+    }
+
+    #[test]
+    fn clear_without_room_for_a_new_head() {
+        use std::alloc::Layout;
+        use crate::arena::{ArenaOptions, BlockAllocator, Global};
+
+        // Hands out a single block, which `clear` gives back for good.
+        struct OneBlock(AtomicBool);
+
+        unsafe impl BlockAllocator for OneBlock {
+            fn allocate(&self, layout: Layout) -> *mut u8 {
+                if self.0.swap(true, Ordering::Relaxed) {
+                    return std::ptr::null_mut();
+                }
+                Global.allocate(layout)
+            }
+
+            unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+                Global.deallocate(ptr, layout)
+            }
+        }
+
+        let options = ArenaOptions { max_retained: Some(0), ..ArenaOptions::default() };
+        let mut list = SkipList::new(Arena::with_options_in(options, OneBlock(AtomicBool::new(false))));
+        for i in 0..10u64 {
+            list.insert(i);
+        }
+        for _ in 0..2 {
+            list.clear();
+            let mut iter = list.iter();
+            iter.seek_to_first();
+            assert!(!iter.valid());
+            assert!(!list.contains(&1));
+            assert!(matches!(list.try_insert(1), Err(Error::ArenaFull)));
+        }
+    }
+
+    #[test]
+    fn memory_stats() {
+        let list = SkipList::new(Arena::new());
+        assert!(list.is_empty());
+        for i in 0..1000u64 {
+            list.insert(i * 7 % 1000);
+        }
+        assert_eq!(list.len(), 1000);
+
+        let stats = list.stats();
+        let node_size = std::mem::size_of::<crate::Node<u64>>();
+        assert_eq!(stats.len, 1000);
+        assert_eq!(stats.key_bytes, 8000);
+        assert_eq!(stats.allocated_bytes, 1001 * node_size);
+        // Every tower has at least one link, and the head has them all.
+        assert!(stats.node_bytes >= 1001 * (node_size - 8) + 1011 * 8);
+        assert_eq!(stats.large_blocks, 0);
+        assert_eq!(stats.blocks, stats.block_bytes / 4096);
+        assert!(stats.unused_bytes() < 4096);
+        assert!(stats.overhead_per_node > node_size as f64);
+        assert!(stats.overhead_per_node < 2.0 * (node_size + 16) as f64);
+
+        let mut list = list;
+        list.clear();
+        let stats = list.stats();
+        assert_eq!((stats.len, stats.key_bytes, stats.overhead_per_node), (0, 0, 0.0));
+        assert_eq!(stats.allocated_bytes, node_size);
+    }
+
+    #[test]
+    fn structure_stats() {
+        let list = SkipList::new(Arena::new());
+        let empty = list.structure_stats();
+        assert_eq!((empty.max_height, empty.len, empty.sampled_keys), (1, 0, 0));
+        assert_eq!(empty.height_histogram, vec![0; crate::MAX_HEIGHT]);
+
+        let n = 100000;
+        for i in 0..n {
+            list.insert(i * 7919 % n);
+        }
+        let stats = list.structure_stats();
+        assert_eq!(stats.len, n as usize);
+        assert_eq!(stats.height_histogram.iter().sum::<usize>(), stats.len);
+        assert_eq!(stats.level_counts[0], stats.len);
+        assert!(stats.level_counts[stats.max_height - 1] > 0);
+        assert!(stats.level_counts[stats.max_height..].iter().all(|&c| c == 0));
+        // Each level holds roughly a quarter of the nodes of the one below.
+        for level in 1..4 {
+            let ratio = stats.level_counts[level] as f64 / stats.level_counts[level - 1] as f64;
+            assert!((0.2..0.3).contains(&ratio), "level {} ratio {}", level, ratio);
+        }
+        assert_eq!(stats.sampled_keys, crate::SEARCH_PATH_SAMPLES);
+        // Logarithmic, far from the linear n / 2.
+        assert!(stats.average_search_path > 5.0 && stats.average_search_path < 60.0);
+    }
+
+    #[test]
+    fn bulk_load_rejects_unsorted_input() {
+        assert!(matches!(SkipList::from_sorted_iter(Arena::new(), [1, 3, 2]), Err(Error::OutOfOrder)));
+        assert!(matches!(SkipList::from_sorted_iter(Arena::new(), [1, 1]), Err(Error::OutOfOrder)));
+
+        let list = SkipList::new(Arena::new());
+        list.insert(10);
+        assert!(matches!(list.extend_sorted([11, 12, 5, 13]), Err(Error::OutOfOrder)));
+        assert!(list.contains(&11));
+        assert!(list.contains(&12));
+        assert!(!list.contains(&5));
+        assert!(!list.contains(&13));
+        assert!(matches!(list.extend_sorted([12]), Err(Error::OutOfOrder)));
+    }
+
     const K: u64 = 4;
 
     type Key = u64;
@@ -665,3 +1347,255 @@ mod tests {
         }
     }
 }
+
+#[cfg(test)]
+mod proptests {
+    use std::collections::BTreeSet;
+    use std::fmt::Debug;
+    use proptest::prelude::*;
+    use crate::arena::Arena;
+    use crate::SkipList;
+
+    trait Key: Ord + Debug + Default + Clone {}
+
+    impl<K: Ord + Debug + Default + Clone> Key for K {}
+
+    fn build<K: Key>(keys: &[K]) -> (SkipList<K>, BTreeSet<K>) {
+        let list = SkipList::new(Arena::new());
+        let mut model = BTreeSet::new();
+        for key in keys {
+            if model.insert(key.clone()) {
+                list.insert(key.clone());
+            }
+        }
+        (list, model)
+    }
+
+    fn iteration_is_sorted<K: Key>(keys: Vec<K>) -> Result<(), TestCaseError> {
+        let (list, model) = build(&keys);
+        let mut iter = list.iter();
+        iter.seek_to_first();
+        for key in &model {
+            prop_assert!(iter.valid());
+            prop_assert_eq!(iter.key(), key);
+            iter.next();
+        }
+        prop_assert!(!iter.valid());
+        prop_assert_eq!(list.len(), model.len());
+        Ok(())
+    }
+
+    fn seek_is_lower_bound<K: Key>(keys: Vec<K>, targets: Vec<K>) -> Result<(), TestCaseError> {
+        let (list, model) = build(&keys);
+        let mut iter = list.iter();
+        for target in &targets {
+            iter.seek(target);
+            prop_assert_eq!(iter.valid().then(|| iter.key()), model.range(target..).next());
+        }
+        Ok(())
+    }
+
+    fn prev_next_round_trip<K: Key>(keys: Vec<K>) -> Result<(), TestCaseError> {
+        let (list, model) = build(&keys);
+        let mut iter = list.iter();
+        for key in &model {
+            iter.seek(key);
+            iter.next();
+            if iter.valid() {
+                iter.prev();
+                prop_assert_eq!(iter.key(), key);
+            }
+            iter.seek(key);
+            iter.prev();
+            if iter.valid() {
+                iter.next();
+                prop_assert_eq!(iter.key(), key);
+            }
+        }
+
+        // Walking back from the last key visits every key in reverse.
+        let mut backwards = Vec::new();
+        iter.seek_to_last();
+        while iter.valid() {
+            backwards.push(iter.key().clone());
+            iter.prev();
+        }
+        prop_assert!(backwards.iter().eq(model.iter().rev()));
+        Ok(())
+    }
+
+    fn contains_matches_model<K: Key>(keys: Vec<K>, probes: Vec<K>) -> Result<(), TestCaseError> {
+        let (list, model) = build(&keys);
+        for key in keys.iter().chain(&probes) {
+            prop_assert_eq!(list.contains(key), model.contains(key));
+        }
+        Ok(())
+    }
+
+    // Small domains, so that keys repeat and probes hit as often as they miss.
+    fn int_key() -> impl Strategy<Value = u64> {
+        0..200u64
+    }
+
+    fn string_key() -> impl Strategy<Value = String> {
+        "[a-d]{0,4}"
+    }
+
+    fn composite_key() -> impl Strategy<Value = (u8, String)> {
+        (0..8u8, "[xy]{0,3}")
+    }
+
+    proptest! {
+        #[test]
+        fn int_iteration_is_sorted(keys in prop::collection::vec(int_key(), 0..300)) {
+            iteration_is_sorted(keys)?;
+        }
+
+        #[test]
+        fn string_iteration_is_sorted(keys in prop::collection::vec(string_key(), 0..300)) {
+            iteration_is_sorted(keys)?;
+        }
+
+        #[test]
+        fn composite_iteration_is_sorted(keys in prop::collection::vec(composite_key(), 0..300)) {
+            iteration_is_sorted(keys)?;
+        }
+
+        #[test]
+        fn int_seek_is_lower_bound(
+            keys in prop::collection::vec(int_key(), 0..300),
+            targets in prop::collection::vec(int_key(), 0..50),
+        ) {
+            seek_is_lower_bound(keys, targets)?;
+        }
+
+        #[test]
+        fn string_seek_is_lower_bound(
+            keys in prop::collection::vec(string_key(), 0..300),
+            targets in prop::collection::vec(string_key(), 0..50),
+        ) {
+            seek_is_lower_bound(keys, targets)?;
+        }
+
+        #[test]
+        fn composite_seek_is_lower_bound(
+            keys in prop::collection::vec(composite_key(), 0..300),
+            targets in prop::collection::vec(composite_key(), 0..50),
+        ) {
+            seek_is_lower_bound(keys, targets)?;
+        }
+
+        #[test]
+        fn int_prev_next_round_trip(keys in prop::collection::vec(int_key(), 0..300)) {
+            prev_next_round_trip(keys)?;
+        }
+
+        #[test]
+        fn string_prev_next_round_trip(keys in prop::collection::vec(string_key(), 0..300)) {
+            prev_next_round_trip(keys)?;
+        }
+
+        #[test]
+        fn composite_prev_next_round_trip(keys in prop::collection::vec(composite_key(), 0..300)) {
+            prev_next_round_trip(keys)?;
+        }
+
+        #[test]
+        fn int_contains_matches_model(
+            keys in prop::collection::vec(int_key(), 0..300),
+            probes in prop::collection::vec(int_key(), 0..50),
+        ) {
+            contains_matches_model(keys, probes)?;
+        }
+
+        #[test]
+        fn string_contains_matches_model(
+            keys in prop::collection::vec(string_key(), 0..300),
+            probes in prop::collection::vec(string_key(), 0..50),
+        ) {
+            contains_matches_model(keys, probes)?;
+        }
+
+        #[test]
+        fn composite_contains_matches_model(
+            keys in prop::collection::vec(composite_key(), 0..300),
+            probes in prop::collection::vec(composite_key(), 0..50),
+        ) {
+            contains_matches_model(keys, probes)?;
+        }
+    }
+}
+
+// Run with `RUSTFLAGS="--cfg loom" cargo test --release --lib loom`.
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use loom::sync::Arc;
+    use loom::thread;
+    use crate::arena::Arena;
+    use crate::test_util::keys;
+    use crate::SkipList;
+
+    // The writer and the reader each get a thread of their own, and the
+    // model's main thread only sets the list up and checks it once both
+    // are joined.
+    fn race<T: Send + 'static>(
+        list: &Arc<SkipList<u64>>,
+        keys: Vec<u64>,
+        read: fn(&SkipList<u64>) -> T,
+    ) -> T {
+        let writer = {
+            let list = Arc::clone(list);
+            thread::spawn(move || {
+                for key in keys {
+                    list.insert(key);
+                }
+            })
+        };
+        let reader = {
+            let list = Arc::clone(list);
+            thread::spawn(move || read(&list))
+        };
+        writer.join().unwrap();
+        reader.join().unwrap()
+    }
+
+    #[test]
+    fn reader_sees_whole_nodes() {
+        loom::model(|| {
+            let list = Arc::new(SkipList::new(Arena::new()));
+            list.insert(10);
+            list.insert(30);
+
+            // Reading a link of a node whose tower is not yet visible is a
+            // causality violation for loom. A node that is reachable must
+            // also link to its successor, or the keys after it go missing.
+            let seen = race(&list, vec![20, 40], |list| keys(list.iter()));
+            assert!(seen.windows(2).all(|w| w[0] < w[1]), "{:?}", seen);
+            assert!(seen.contains(&10) && seen.contains(&30), "{:?}", seen);
+            assert!(seen.iter().all(|k| [10, 20, 30, 40].contains(k)), "{:?}", seen);
+            assert_eq!(keys(list.iter()), [10, 20, 30, 40]);
+        });
+    }
+
+    fn max_height(list: &SkipList<u64>) -> usize {
+        unsafe { (*list.skip_list.get()).get_max_height() }
+    }
+
+    #[test]
+    fn lookups_during_insert() {
+        loom::model(|| {
+            // With the list's fixed seed the first node has a height of one
+            // and the second a height of two.
+            let list = Arc::new(SkipList::new(Arena::new()));
+            list.insert(2);
+            assert_eq!(max_height(&list), 1);
+
+            // Searches start from the upper levels, and max_height is raised
+            // before the new node is linked on them.
+            let found = race(&list, vec![4], |list| (list.contains(&2), list.contains(&3), list.contains(&5)));
+            assert_eq!(found, (true, false, false));
+            assert_eq!(max_height(&list), 2);
+            assert!(list.contains(&4));
+        });
+    }
+}
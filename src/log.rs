@@ -0,0 +1,313 @@
+//! Record-oriented log files in LevelDB's log format.
+//!
+//! The file is a sequence of 32 KiB blocks. A record is split into fragments
+//! that never cross a block boundary, and each fragment has a header:
+//!
+//! ```text
+//! checksum: fixed32 (masked crc32c of type and payload) | length: fixed16 | type: u8 | payload
+//! ```
+//!
+//! `type` tells whether the fragment holds a full record or its first,
+//! middle or last part. A block tail too small for a header is zero-filled.
+
+use std::io::{self, Read, Write};
+use std::ops::Range;
+
+use crate::coding::decode_fixed32;
+use crate::crc32c;
+use crate::error::{Error, Result};
+
+pub const BLOCK_SIZE: usize = 32768;
+pub const HEADER_SIZE: usize = 7;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum RecordType {
+    Full = 1,
+    First = 2,
+    Middle = 3,
+    Last = 4,
+}
+
+impl RecordType {
+    fn from_u8(t: u8) -> Option<Self> {
+        match t {
+            1 => Some(RecordType::Full),
+            2 => Some(RecordType::First),
+            3 => Some(RecordType::Middle),
+            4 => Some(RecordType::Last),
+            _ => None,
+        }
+    }
+}
+
+pub struct Writer<W: Write> {
+    dest: W,
+    block_offset: usize,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(dest: W) -> Self {
+        Writer { dest, block_offset: 0 }
+    }
+
+    /// Creates a writer that appends to a log which is already `dest_length`
+    /// bytes long.
+    pub fn with_offset(dest: W, dest_length: u64) -> Self {
+        Writer { dest, block_offset: (dest_length % BLOCK_SIZE as u64) as usize }
+    }
+
+    pub fn add_record(&mut self, mut data: &[u8]) -> io::Result<()> {
+        // An empty record is still written as one zero-length fragment.
+        let mut begin = true;
+        loop {
+            let leftover = BLOCK_SIZE - self.block_offset;
+            if leftover < HEADER_SIZE {
+                // Switch to a new block, zero-filling the trailer.
+                self.dest.write_all(&[0; HEADER_SIZE][..leftover])?;
+                self.block_offset = 0;
+            }
+
+            let avail = BLOCK_SIZE - self.block_offset - HEADER_SIZE;
+            let fragment_length = data.len().min(avail);
+            let end = fragment_length == data.len();
+            let record_type = match (begin, end) {
+                (true, true) => RecordType::Full,
+                (true, false) => RecordType::First,
+                (false, true) => RecordType::Last,
+                (false, false) => RecordType::Middle,
+            };
+
+            self.emit_physical_record(record_type, &data[..fragment_length])?;
+            data = &data[fragment_length..];
+            begin = false;
+            if end {
+                return Ok(());
+            }
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.dest.flush()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.dest
+    }
+
+    pub fn into_inner(self) -> W {
+        self.dest
+    }
+
+    fn emit_physical_record(&mut self, t: RecordType, data: &[u8]) -> io::Result<()> {
+        let crc = crc32c::extend(crc32c::value(&[t as u8]), data);
+        let mut header = [0; HEADER_SIZE];
+        header[..4].copy_from_slice(&crc32c::mask(crc).to_le_bytes());
+        header[4..6].copy_from_slice(&(data.len() as u16).to_le_bytes());
+        header[6] = t as u8;
+
+        self.dest.write_all(&header)?;
+        self.dest.write_all(data)?;
+        self.block_offset += HEADER_SIZE + data.len();
+        Ok(())
+    }
+}
+
+pub struct Reader<R: Read> {
+    src: R,
+    block: Vec<u8>,
+    // Read position within `block`.
+    pos: usize,
+    // File offset of the start of `block`.
+    block_start: u64,
+    eof: bool,
+    last_record_end: u64,
+}
+
+impl<R: Read> Reader<R> {
+    pub fn new(src: R) -> Self {
+        Reader { src, block: Vec::new(), pos: 0, block_start: 0, eof: false, last_record_end: 0 }
+    }
+
+    /// Reads the next record, or returns `None` at the end of the log.
+    ///
+    /// A record cut short by the end of the file is what a crash in the
+    /// middle of a write leaves behind; it is dropped and treated as the end
+    /// of the log. Damage anywhere else is reported as corruption.
+    pub fn read_record(&mut self) -> Result<Option<Vec<u8>>> {
+        let mut record = Vec::new();
+        let mut in_fragmented_record = false;
+        loop {
+            let (t, range) = match self.read_physical_record()? {
+                Some(r) => r,
+                None => return Ok(None),
+            };
+            let fragment = &self.block[range];
+            match t {
+                RecordType::Full if !in_fragmented_record => {
+                    let record = fragment.to_vec();
+                    self.last_record_end = self.offset();
+                    return Ok(Some(record));
+                }
+                RecordType::First if !in_fragmented_record => {
+                    record.extend_from_slice(fragment);
+                    in_fragmented_record = true;
+                }
+                RecordType::Middle if in_fragmented_record => {
+                    record.extend_from_slice(fragment);
+                }
+                RecordType::Last if in_fragmented_record => {
+                    record.extend_from_slice(fragment);
+                    self.last_record_end = self.offset();
+                    return Ok(Some(record));
+                }
+                _ => return Err(Error::Corruption(format!("unexpected {:?} fragment in log", t))),
+            }
+        }
+    }
+
+    /// File offset just past the last complete record returned.
+    pub fn last_record_end(&self) -> u64 {
+        self.last_record_end
+    }
+
+    fn offset(&self) -> u64 {
+        self.block_start + self.pos as u64
+    }
+
+    fn read_physical_record(&mut self) -> Result<Option<(RecordType, Range<usize>)>> {
+        loop {
+            if self.block.len() - self.pos < HEADER_SIZE {
+                if self.eof {
+                    // Either a clean end or a truncated header.
+                    return Ok(None);
+                }
+                // Skip the zero-filled trailer and read the next block.
+                self.block_start += self.block.len() as u64;
+                self.block.clear();
+                self.pos = 0;
+                self.src.by_ref().take(BLOCK_SIZE as u64).read_to_end(&mut self.block)?;
+                if self.block.len() < BLOCK_SIZE {
+                    self.eof = true;
+                }
+                continue;
+            }
+
+            let header = &self.block[self.pos..self.pos + HEADER_SIZE];
+            let length = u16::from_le_bytes([header[4], header[5]]) as usize;
+            let t = header[6];
+            if self.pos + HEADER_SIZE + length > self.block.len() {
+                if self.eof {
+                    // The writer died in the middle of the record.
+                    return Ok(None);
+                }
+                return Err(Error::Corruption("bad record length in log".into()));
+            }
+            if t == 0 && length == 0 {
+                // Zero padding left by a preallocating file system.
+                self.pos = self.block.len();
+                continue;
+            }
+
+            let expected = crc32c::unmask(decode_fixed32(header));
+            let start = self.pos + HEADER_SIZE;
+            let payload = &self.block[start..start + length];
+            if crc32c::extend(crc32c::value(&[t]), payload) != expected {
+                return Err(Error::Corruption("checksum mismatch in log".into()));
+            }
+            let t = RecordType::from_u8(t)
+                .ok_or_else(|| Error::Corruption(format!("unknown log record type {}", t)))?;
+            self.pos = start + length;
+            return Ok(Some((t, start..start + length)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use super::{Reader, Writer, BLOCK_SIZE, HEADER_SIZE};
+
+    fn big_string(partial: &str, n: usize) -> Vec<u8> {
+        partial.bytes().cycle().take(n).collect()
+    }
+
+    fn write(records: &[Vec<u8>]) -> Vec<u8> {
+        let mut writer = Writer::new(Vec::new());
+        for r in records {
+            writer.add_record(r).unwrap();
+        }
+        writer.dest
+    }
+
+    fn read_all(data: &[u8]) -> Vec<Vec<u8>> {
+        let mut reader = Reader::new(data);
+        let mut records = Vec::new();
+        while let Some(r) = reader.read_record().unwrap() {
+            records.push(r);
+        }
+        records
+    }
+
+    #[test]
+    fn test_empty() {
+        assert!(read_all(&[]).is_empty());
+    }
+
+    #[test]
+    fn read_write() {
+        let records = vec![b"foo".to_vec(), b"bar".to_vec(), Vec::new(), b"xxxx".to_vec()];
+        assert_eq!(read_all(&write(&records)), records);
+    }
+
+    #[test]
+    fn fragmentation() {
+        let records = vec![b"small".to_vec(), big_string("medium", 50000), big_string("large", 100000)];
+        let data = write(&records);
+        assert_eq!(read_all(&data), records);
+    }
+
+    #[test]
+    fn marginal_trailer() {
+        // Leave exactly enough room in the first block for one empty header,
+        // then fewer bytes than a header.
+        for n in [BLOCK_SIZE - 2 * HEADER_SIZE, BLOCK_SIZE - 2 * HEADER_SIZE + 1] {
+            let records = vec![big_string("foo", n), Vec::new(), b"bar".to_vec()];
+            let data = write(&records);
+            assert_eq!(read_all(&data), records);
+        }
+    }
+
+    #[test]
+    fn append_after_reopen() {
+        let mut data = write(&[big_string("foo", 40000)]);
+        let len = data.len() as u64;
+        let mut writer = Writer::with_offset(&mut data, len);
+        writer.add_record(&big_string("bar", 30000)).unwrap();
+        assert_eq!(read_all(&data), [big_string("foo", 40000), big_string("bar", 30000)]);
+    }
+
+    #[test]
+    fn torn_tail() {
+        let records = vec![b"foo".to_vec(), big_string("bar", 40000), b"baz".to_vec()];
+        let data = write(&records);
+        for cut in 1..HEADER_SIZE + 3 {
+            let truncated = &data[..data.len() - cut];
+            assert_eq!(read_all(truncated), &records[..2]);
+
+            let mut reader = Reader::new(truncated);
+            while reader.read_record().unwrap().is_some() {}
+            assert_eq!(reader.last_record_end(), (data.len() - 3 - HEADER_SIZE) as u64);
+        }
+        // Losing the last fragment of a record spanning two blocks.
+        let data = write(&records[..2]);
+        assert_eq!(read_all(&data[..BLOCK_SIZE + 10]), &records[..1]);
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let mut data = write(&[b"foo".to_vec(), b"bar".to_vec()]);
+        data[HEADER_SIZE] ^= 1;
+        let mut reader = Reader::new(&data[..]);
+        assert!(matches!(reader.read_record(), Err(Error::Corruption(_))));
+    }
+}
@@ -0,0 +1,271 @@
+use std::borrow::Borrow;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt::Debug;
+
+use crate::arena::{BlockAllocator, Global};
+use crate::SkipListIterator;
+
+#[derive(PartialEq, Eq)]
+enum Direction {
+    Forward,
+    Reverse,
+}
+
+/// Merges several skip list iterators into one ordered view.
+///
+/// Children are given newest first. A key present in more than one child is
+/// yielded once, and `source` reports the newest child that holds it.
+pub struct MergingIterator<'a, K: Ord + Debug + Default, A: BlockAllocator = Global> {
+    children: Vec<SkipListIterator<'a, K, A>>,
+    direction: Direction,
+    forward: BinaryHeap<Reverse<(&'a K, usize)>>,
+    backward: BinaryHeap<(&'a K, Reverse<usize>)>,
+}
+
+impl<'a, K: Ord + Debug + Default, A: BlockAllocator> MergingIterator<'a, K, A> {
+    pub fn new(children: Vec<SkipListIterator<'a, K, A>>) -> Self {
+        MergingIterator {
+            children,
+            direction: Direction::Forward,
+            forward: BinaryHeap::new(),
+            backward: BinaryHeap::new(),
+        }
+    }
+
+    pub fn valid(&self) -> bool {
+        match self.direction {
+            Direction::Forward => !self.forward.is_empty(),
+            Direction::Reverse => !self.backward.is_empty(),
+        }
+    }
+
+    pub fn key(&self) -> &'a K {
+        self.current().0
+    }
+
+    /// Index of the child the current key is taken from.
+    pub fn source(&self) -> usize {
+        self.current().1
+    }
+
+    pub fn next(&mut self) {
+        assert!(self.valid());
+        if self.direction == Direction::Reverse {
+            // Position every child after the current key. The result is
+            // already the next entry, so there is nothing left to advance.
+            let key = self.key();
+            for child in &mut self.children {
+                child.seek(key);
+                if child.valid() && child.key() == key {
+                    child.next();
+                }
+            }
+            self.rebuild_forward();
+            return;
+        }
+
+        let key = self.key();
+        while let Some(&Reverse((k, i))) = self.forward.peek() {
+            if k != key {
+                break;
+            }
+            self.forward.pop();
+            let child = &mut self.children[i];
+            child.next();
+            if child.valid() {
+                self.forward.push(Reverse((child.key(), i)));
+            }
+        }
+    }
+
+    pub fn prev(&mut self) {
+        assert!(self.valid());
+        if self.direction == Direction::Forward {
+            // Position every child at its last entry before the current key.
+            let key = self.key();
+            for child in &mut self.children {
+                child.seek(key);
+                if child.valid() {
+                    child.prev();
+                } else {
+                    child.seek_to_last();
+                }
+            }
+            self.rebuild_backward();
+            return;
+        }
+
+        let key = self.key();
+        while let Some(&(k, Reverse(i))) = self.backward.peek() {
+            if k != key {
+                break;
+            }
+            self.backward.pop();
+            let child = &mut self.children[i];
+            child.prev();
+            if child.valid() {
+                self.backward.push((child.key(), Reverse(i)));
+            }
+        }
+    }
+
+    pub fn seek<Q>(&mut self, target: &Q)
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        for child in &mut self.children {
+            child.seek(target);
+        }
+        self.rebuild_forward();
+    }
+
+    pub fn seek_to_first(&mut self) {
+        for child in &mut self.children {
+            child.seek_to_first();
+        }
+        self.rebuild_forward();
+    }
+
+    pub fn seek_to_last(&mut self) {
+        for child in &mut self.children {
+            child.seek_to_last();
+        }
+        self.rebuild_backward();
+    }
+
+    fn current(&self) -> (&'a K, usize) {
+        assert!(self.valid());
+        match self.direction {
+            Direction::Forward => self.forward.peek().map(|&Reverse((k, i))| (k, i)).unwrap(),
+            Direction::Reverse => self.backward.peek().map(|&(k, Reverse(i))| (k, i)).unwrap(),
+        }
+    }
+
+    fn rebuild_forward(&mut self) {
+        self.direction = Direction::Forward;
+        self.forward.clear();
+        for (i, child) in self.children.iter().enumerate() {
+            if child.valid() {
+                self.forward.push(Reverse((child.key(), i)));
+            }
+        }
+    }
+
+    fn rebuild_backward(&mut self) {
+        self.direction = Direction::Reverse;
+        self.backward.clear();
+        for (i, child) in self.children.iter().enumerate() {
+            if child.valid() {
+                self.backward.push((child.key(), Reverse(i)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use rand::prelude::StdRng;
+    use rand::{Rng, SeedableRng};
+    use crate::arena::Arena;
+    use crate::SkipList;
+    use super::MergingIterator;
+
+    #[test]
+    fn test_empty() {
+        let mut iter = MergingIterator::<u64>::new(vec![]);
+        iter.seek_to_first();
+        assert!(!iter.valid());
+        iter.seek_to_last();
+        assert!(!iter.valid());
+
+        let list = SkipList::new(Arena::new());
+        let mut iter = MergingIterator::<u64>::new(vec![list.iter(), list.iter()]);
+        iter.seek(&10);
+        assert!(!iter.valid());
+    }
+
+    #[test]
+    fn merge_matches_model() {
+        let mut rng = StdRng::seed_from_u64(301);
+        let lists: Vec<_> = (0..4).map(|_| SkipList::new(Arena::new())).collect();
+        // Maps every key to the newest list that contains it.
+        let mut model = BTreeMap::new();
+        for (i, list) in lists.iter().enumerate() {
+            let mut keys = std::collections::BTreeSet::new();
+            for _ in 0..500 {
+                keys.insert(rng.gen_range(0..2000u64));
+            }
+            for k in keys {
+                list.insert(k);
+                model.entry(k).or_insert(i);
+            }
+        }
+
+        let mut iter = MergingIterator::new(lists.iter().map(|l| l.iter()).collect());
+        iter.seek_to_first();
+        for (k, source) in &model {
+            assert!(iter.valid());
+            assert_eq!(iter.key(), k);
+            assert_eq!(iter.source(), *source);
+            iter.next();
+        }
+        assert!(!iter.valid());
+
+        iter.seek_to_last();
+        for (k, source) in model.iter().rev() {
+            assert!(iter.valid());
+            assert_eq!(iter.key(), k);
+            assert_eq!(iter.source(), *source);
+            iter.prev();
+        }
+        assert!(!iter.valid());
+
+        for target in 0..2001 {
+            iter.seek(&target);
+            match model.range(target..).next() {
+                Some((k, _)) => assert_eq!(iter.key(), k),
+                None => assert!(!iter.valid()),
+            }
+        }
+    }
+
+    #[test]
+    fn change_direction() {
+        let newer = SkipList::new(Arena::new());
+        let older = SkipList::new(Arena::new());
+        for k in [1, 3, 5, 7] {
+            older.insert(k);
+        }
+        for k in [2, 3, 6, 7] {
+            newer.insert(k);
+        }
+
+        let mut iter = MergingIterator::new(vec![newer.iter(), older.iter()]);
+        iter.seek(&3);
+        assert_eq!((*iter.key(), iter.source()), (3, 0));
+        iter.next();
+        assert_eq!(*iter.key(), 5);
+        iter.prev();
+        assert_eq!((*iter.key(), iter.source()), (3, 0));
+        iter.prev();
+        assert_eq!(*iter.key(), 2);
+        iter.next();
+        assert_eq!(*iter.key(), 3);
+        iter.next();
+        assert_eq!(*iter.key(), 5);
+        iter.next();
+        assert_eq!(*iter.key(), 6);
+
+        iter.seek_to_last();
+        assert_eq!((*iter.key(), iter.source()), (7, 0));
+        iter.next();
+        assert!(!iter.valid());
+
+        iter.seek_to_first();
+        iter.prev();
+        assert!(!iter.valid());
+    }
+}
@@ -0,0 +1,484 @@
+//! A bump arena that lives in one memory-mapped region.
+//!
+//! The region is either a file, so the OS can page it out and a later process
+//! can reattach to it, a POSIX shared-memory object that other processes can
+//! map at the same time, or anonymous memory. Unlike `Arena` it never grows:
+//! the mapping has a fixed capacity chosen at creation, which keeps every
+//! allocation at a stable offset from the start of the region. Data that
+//! refers to other allocations by offset (see `offset_of` and `get`) stays
+//! valid wherever the region is mapped.
+//!
+//! The region starts with a header:
+//!
+//! ```text
+//! magic: u64 | version: u32 | reserved: u32 | capacity: u64 | allocated: u64 | root: u64
+//! ```
+//!
+//! `allocated` is the bump offset, so reopening a file resumes allocation
+//! after the existing data. `root` is a free slot for the offset of whatever
+//! structure the caller needs to find again.
+//!
+//! A file or shared-memory object has at most one writable mapping at a
+//! time: writable arenas hold an exclusive `flock` on it until they are
+//! dropped, and opening a second one fails. Read-only arenas take no lock.
+//!
+//! `MmapArena` is also a `BlockAllocator`, so an `Arena`, and with it a
+//! `SkipList`, can take its blocks from the region:
+//!
+//! ```
+//! use skiplist_rust::arena::Arena;
+//! use skiplist_rust::mmap_arena::MmapArena;
+//! use skiplist_rust::SkipList;
+//!
+//! let list = SkipList::new(Arena::new_in(MmapArena::anonymous(1 << 20).unwrap()));
+//! list.insert(42u64);
+//! ```
+//!
+//! Such a list holds absolute pointers, so it cannot be reattached from a
+//! file; `OffsetSkipList` is the list for that.
+
+use std::alloc::Layout;
+use std::ffi::CString;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::Path;
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::arena::BlockAllocator;
+use crate::error::{Error, Result};
+
+const MAGIC: u64 = 0x414e_4552_414c_4b53; // "SKLARENA"
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 64;
+const ALIGN: usize = if std::mem::size_of::<*mut ()>() > 8 { std::mem::size_of::<*mut ()>() } else { 8 };
+const PROT_READ_WRITE: libc::c_int = libc::PROT_READ | libc::PROT_WRITE;
+
+#[repr(C)]
+struct Header {
+    magic: u64,
+    version: u32,
+    reserved: u32,
+    capacity: u64,
+    allocated: AtomicU64,
+    root: AtomicU64,
+}
+
+pub struct MmapArena {
+    base: *mut u8,
+    capacity: usize,
+    file: Option<File>,
+    writable: bool,
+}
+
+unsafe impl Send for MmapArena {}
+
+impl MmapArena {
+    /// Creates `path` with room for `capacity` bytes, header included,
+    /// replacing any existing file that no other arena has open for writing.
+    pub fn create<P: AsRef<Path>>(path: P, capacity: usize) -> Result<Self> {
+        assert!(capacity > HEADER_SIZE);
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        // Lock before truncating, so that a live writer's file is left alone.
+        lock_exclusive(&file)?;
+        file.set_len(0)?;
+        file.set_len(capacity as u64)?;
+        let base = map(capacity, PROT_READ_WRITE, libc::MAP_SHARED, file.as_raw_fd())?;
+        let arena = MmapArena { base, capacity, file: Some(file), writable: true };
+        arena.init_header();
+        Ok(arena)
+    }
+
+    /// Maps an existing arena file and validates its header. Fails if another
+    /// arena has the file open for writing.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::open_file(path.as_ref(), true)
+    }
+
+    /// Maps an existing arena file read-only. Allocating from the returned
+    /// arena, or setting its root, panics.
+    pub fn open_read_only<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::open_file(path.as_ref(), false)
+    }
+
+    fn open_file(path: &Path, writable: bool) -> Result<Self> {
+        let file = OpenOptions::new().read(true).write(writable).open(path)?;
+        Self::attach(file, writable, "arena file is too short")
+    }
+
+    /// Creates the shared-memory object `name` (for example `/my-index`) with
+    /// room for `capacity` bytes. Fails if the object already exists.
+    ///
+    /// The object outlives the arena until `unlink_shm` removes it.
+    pub fn create_shm(name: &str, capacity: usize) -> Result<Self> {
+        assert!(capacity > HEADER_SIZE);
+        let file = shm_open(name, libc::O_RDWR | libc::O_CREAT | libc::O_EXCL)?;
+        lock_exclusive(&file)?;
+        file.set_len(capacity as u64)?;
+        let base = map(capacity, PROT_READ_WRITE, libc::MAP_SHARED, file.as_raw_fd())?;
+        let arena = MmapArena { base, capacity, file: Some(file), writable: true };
+        arena.init_header();
+        Ok(arena)
+    }
+
+    /// Maps the existing shared-memory object `name` for reading and writing.
+    /// Fails if another arena has it open for writing.
+    pub fn open_shm(name: &str) -> Result<Self> {
+        Self::attach_shm(name, true)
+    }
+
+    /// Maps the existing shared-memory object `name` read-only. Allocating
+    /// from the returned arena, or setting its root, panics.
+    pub fn open_shm_read_only(name: &str) -> Result<Self> {
+        Self::attach_shm(name, false)
+    }
+
+    /// Removes the shared-memory object `name`. Arenas that have it mapped
+    /// keep working.
+    pub fn unlink_shm(name: &str) -> Result<()> {
+        let name = shm_name(name)?;
+        if unsafe { libc::shm_unlink(name.as_ptr()) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    fn attach_shm(name: &str, writable: bool) -> Result<Self> {
+        let file = shm_open(name, if writable { libc::O_RDWR } else { libc::O_RDONLY })?;
+        Self::attach(file, writable, "shared memory object is too short")
+    }
+
+    fn attach(file: File, writable: bool, too_short: &str) -> Result<Self> {
+        if writable {
+            lock_exclusive(&file)?;
+        }
+        let capacity = file.metadata()?.len() as usize;
+        if capacity < HEADER_SIZE {
+            return Err(Error::Corruption(too_short.into()));
+        }
+        let prot = if writable { PROT_READ_WRITE } else { libc::PROT_READ };
+        let base = map(capacity, prot, libc::MAP_SHARED, file.as_raw_fd())?;
+        let arena = MmapArena { base, capacity, file: Some(file), writable };
+        arena.validate_header()?;
+        Ok(arena)
+    }
+
+    /// Creates an arena in anonymous memory that is not backed by a file.
+    pub fn anonymous(capacity: usize) -> Result<Self> {
+        assert!(capacity > HEADER_SIZE);
+        let base = map(capacity, PROT_READ_WRITE, libc::MAP_PRIVATE | libc::MAP_ANONYMOUS, -1)?;
+        let arena = MmapArena { base, capacity, file: None, writable: true };
+        arena.init_header();
+        Ok(arena)
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the arena does not have `bytes` left.
+    pub fn allocate(&mut self, bytes: usize) -> *mut u8 {
+        self.try_allocate(bytes).unwrap_or_else(|_| self.full())
+    }
+
+    pub fn allocate_aligned(&mut self, bytes: usize) -> *mut u8 {
+        self.try_allocate_aligned(bytes).unwrap_or_else(|_| self.full())
+    }
+
+    /// Like `allocate`, but returns `Error::ArenaFull` if the arena does not
+    /// have `bytes` left.
+    pub fn try_allocate(&mut self, bytes: usize) -> Result<*mut u8> {
+        assert!(bytes > 0);
+        self.bump(1, bytes)
+    }
+
+    pub fn try_allocate_aligned(&mut self, bytes: usize) -> Result<*mut u8> {
+        assert!(bytes > 0);
+        self.bump(ALIGN, bytes)
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.header().allocated.load(Ordering::Relaxed) as usize
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Offset of `ptr` from the start of the region.
+    pub fn offset_of(&self, ptr: *const u8) -> usize {
+        let offset = (ptr as usize).wrapping_sub(self.base as usize);
+        assert!(offset < self.capacity);
+        offset
+    }
+
+    /// Pointer to the byte at `offset` from the start of the region.
+    pub fn get(&self, offset: usize) -> *mut u8 {
+        assert!(offset < self.capacity);
+        unsafe { self.base.add(offset) }
+    }
+
+    pub fn root(&self) -> usize {
+        self.header().root.load(Ordering::Acquire) as usize
+    }
+
+    pub fn set_root(&self, offset: usize) {
+        assert!(self.writable, "arena is read-only");
+        self.header().root.store(offset as u64, Ordering::Release);
+    }
+
+    /// Writes dirty pages back to the file.
+    pub fn flush(&self) -> Result<()> {
+        if self.file.is_some() && unsafe { libc::msync(self.base.cast(), self.capacity, libc::MS_SYNC) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    fn header(&self) -> &Header {
+        unsafe { &*(self.base as *const Header) }
+    }
+
+    fn bump(&self, align: usize, bytes: usize) -> Result<*mut u8> {
+        assert!(self.writable, "arena is read-only");
+        let offset = self.header().allocated.load(Ordering::Relaxed) as usize;
+        let start = offset + ((self.base as usize + offset).wrapping_neg() & (align - 1));
+        let end = start.checked_add(bytes).filter(|&end| end <= self.capacity).ok_or(Error::ArenaFull)?;
+        self.header().allocated.store(end as u64, Ordering::Relaxed);
+        Ok(unsafe { self.base.add(start) })
+    }
+
+    fn full(&self) -> ! {
+        panic!("arena is full: {} of {} bytes used", self.memory_usage(), self.capacity)
+    }
+
+    fn init_header(&self) {
+        unsafe {
+            ptr::write(self.base as *mut Header, Header {
+                magic: MAGIC,
+                version: VERSION,
+                reserved: 0,
+                capacity: self.capacity as u64,
+                allocated: AtomicU64::new(HEADER_SIZE as u64),
+                root: AtomicU64::new(0),
+            });
+        }
+    }
+
+    fn validate_header(&self) -> Result<()> {
+        let header = self.header();
+        if header.magic != MAGIC {
+            return Err(Error::Corruption("not an arena file (bad magic number)".into()));
+        }
+        if header.version != VERSION {
+            return Err(Error::Corruption(format!("unsupported arena version {}", header.version)));
+        }
+        if header.capacity != self.capacity as u64 {
+            return Err(Error::Corruption(format!(
+                "arena header says {} bytes but the file has {}", header.capacity, self.capacity)));
+        }
+        let allocated = header.allocated.load(Ordering::Relaxed);
+        if allocated < HEADER_SIZE as u64 || allocated > header.capacity {
+            return Err(Error::Corruption("arena allocation offset is out of range".into()));
+        }
+        if header.root.load(Ordering::Relaxed) >= allocated {
+            return Err(Error::Corruption("arena root offset is out of range".into()));
+        }
+        Ok(())
+    }
+}
+
+/// Blocks are carved from the region like any other allocation and are not
+/// reused: their space comes back only when the region is unmapped.
+unsafe impl BlockAllocator for MmapArena {
+    fn allocate(&self, layout: Layout) -> *mut u8 {
+        if !self.writable || layout.size() == 0 {
+            return ptr::null_mut();
+        }
+        self.bump(layout.align(), layout.size()).unwrap_or(ptr::null_mut())
+    }
+
+    unsafe fn deallocate(&self, _ptr: *mut u8, _layout: Layout) {}
+}
+
+impl Drop for MmapArena {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.base.cast(), self.capacity);
+        }
+    }
+}
+
+fn map(len: usize, prot: libc::c_int, flags: libc::c_int, fd: libc::c_int) -> io::Result<*mut u8> {
+    let ptr = unsafe { libc::mmap(ptr::null_mut(), len, prot, flags, fd, 0) };
+    if ptr == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(ptr.cast())
+}
+
+/// Takes the writer's lock on `file`, which is released when it is closed.
+fn lock_exclusive(file: &File) -> Result<()> {
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+        let e = io::Error::last_os_error();
+        if e.kind() == io::ErrorKind::WouldBlock {
+            return Err(io::Error::new(e.kind(), "arena is already open for writing").into());
+        }
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+fn shm_name(name: &str) -> io::Result<CString> {
+    CString::new(name).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+fn shm_open(name: &str, flags: libc::c_int) -> io::Result<File> {
+    let name = shm_name(name)?;
+    let fd = unsafe { libc::shm_open(name.as_ptr(), flags, 0o600 as libc::mode_t) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::arena::Arena;
+    use crate::error::Error;
+    use crate::test_util::{keys, TempPath};
+    use crate::SkipList;
+    use super::{MmapArena, HEADER_SIZE};
+
+    #[test]
+    fn allocate_and_reopen() {
+        let path = TempPath::new("reopen");
+        let mut offsets = Vec::new();
+        {
+            let mut arena = MmapArena::create(&path.0, 1 << 20).unwrap();
+            assert_eq!(arena.memory_usage(), HEADER_SIZE);
+            for i in 1..200usize {
+                let p = if i % 3 == 0 { arena.allocate_aligned(i) } else { arena.allocate(i) };
+                if i % 3 == 0 {
+                    assert_eq!(p as usize % 8, 0);
+                }
+                unsafe { std::ptr::write_bytes(p, i as u8, i) };
+                offsets.push((arena.offset_of(p), i));
+            }
+            arena.set_root(offsets[10].0);
+            arena.flush().unwrap();
+        }
+
+        let mut arena = MmapArena::open(&path.0).unwrap();
+        assert_eq!(arena.root(), offsets[10].0);
+        for &(offset, n) in &offsets {
+            let bytes = unsafe { std::slice::from_raw_parts(arena.get(offset), n) };
+            assert!(bytes.iter().all(|&b| b == n as u8));
+        }
+        let used = arena.memory_usage();
+        let p = arena.allocate(10);
+        assert_eq!(arena.offset_of(p), used);
+    }
+
+    #[test]
+    fn header_validation() {
+        let path = TempPath::new("header");
+        MmapArena::create(&path.0, 4096).unwrap();
+
+        let mut data = fs::read(&path.0).unwrap();
+        data[0] ^= 1;
+        fs::write(&path.0, &data).unwrap();
+        assert!(matches!(MmapArena::open(&path.0), Err(Error::Corruption(_))));
+
+        data[0] ^= 1;
+        data.truncate(2048);
+        fs::write(&path.0, &data).unwrap();
+        assert!(matches!(MmapArena::open(&path.0), Err(Error::Corruption(_))));
+
+        fs::write(&path.0, &data[..10]).unwrap();
+        assert!(matches!(MmapArena::open(&path.0), Err(Error::Corruption(_))));
+
+        let missing = TempPath::new("missing");
+        assert!(matches!(MmapArena::open(&missing.0), Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn single_writer() {
+        let path = TempPath::new("writer");
+        let writer = MmapArena::create(&path.0, 4096).unwrap();
+        assert!(matches!(MmapArena::open(&path.0), Err(Error::Io(_))));
+        assert!(matches!(MmapArena::create(&path.0, 4096), Err(Error::Io(_))));
+        let reader = MmapArena::open_read_only(&path.0).unwrap();
+        assert_eq!(reader.memory_usage(), HEADER_SIZE);
+        drop(writer);
+        MmapArena::open(&path.0).unwrap();
+
+        let name = format!("/skiplist-arena-writer-test-{}", std::process::id());
+        let _writer = MmapArena::create_shm(&name, 8192).unwrap();
+        let result = MmapArena::open_shm(&name);
+        MmapArena::unlink_shm(&name).unwrap();
+        assert!(matches!(result, Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn anonymous() {
+        let mut arena = MmapArena::anonymous(4096).unwrap();
+        let p = arena.allocate(4096 - HEADER_SIZE);
+        unsafe { std::ptr::write_bytes(p, 0xab, 4096 - HEADER_SIZE) };
+        assert_eq!(arena.memory_usage(), 4096);
+    }
+
+    #[test]
+    fn shared_memory() {
+        let name = format!("/skiplist-arena-test-{}", std::process::id());
+        let mut writer = MmapArena::create_shm(&name, 8192).unwrap();
+        assert!(MmapArena::create_shm(&name, 8192).is_err());
+        let reader = MmapArena::open_shm_read_only(&name).unwrap();
+        MmapArena::unlink_shm(&name).unwrap();
+        assert!(matches!(MmapArena::open_shm(&name), Err(Error::Io(_))));
+
+        // Both mappings see the same memory after the name is gone.
+        let p = writer.allocate(100);
+        unsafe { std::ptr::write_bytes(p, 7, 100) };
+        writer.set_root(writer.offset_of(p));
+        assert_eq!(reader.root(), writer.root());
+        assert_eq!(reader.memory_usage(), writer.memory_usage());
+        let bytes = unsafe { std::slice::from_raw_parts(reader.get(reader.root()), 100) };
+        assert!(bytes.iter().all(|&b| b == 7));
+    }
+
+    #[test]
+    #[should_panic(expected = "arena is read-only")]
+    fn read_only() {
+        let name = format!("/skiplist-arena-ro-test-{}", std::process::id());
+        let _writer = MmapArena::create_shm(&name, 8192).unwrap();
+        let mut reader = MmapArena::open_shm_read_only(&name).unwrap();
+        MmapArena::unlink_shm(&name).unwrap();
+        reader.allocate(10);
+    }
+
+    #[test]
+    #[should_panic(expected = "arena is full")]
+    fn full() {
+        let mut arena = MmapArena::anonymous(4096).unwrap();
+        assert!(matches!(arena.try_allocate(4096), Err(Error::ArenaFull)));
+        assert!(matches!(arena.try_allocate_aligned(4096), Err(Error::ArenaFull)));
+        assert_eq!(arena.memory_usage(), HEADER_SIZE);
+        arena.allocate(4096);
+    }
+
+    #[test]
+    fn backs_a_skiplist() {
+        let list = SkipList::new(Arena::new_in(MmapArena::anonymous(1 << 16).unwrap()));
+        let mut inserted = Vec::new();
+        for i in 0.. {
+            match list.try_insert(i) {
+                Ok(()) => inserted.push(i),
+                Err(Error::ArenaFull) => break,
+                Err(e) => panic!("{}", e),
+            }
+        }
+        assert!(inserted.len() > 100);
+        assert_eq!(keys(list.iter()), inserted);
+    }
+}
@@ -0,0 +1,583 @@
+//! A skip list of byte-string keys whose nodes link to each other by `u32`
+//! offsets into an `MmapArena` instead of by pointers.
+//!
+//! Everything the list needs, keys included, lives inside the arena, so the
+//! arena image is position-independent: it can be written to disk, mapped at
+//! a different address by another process, or copied, and it still forms a
+//! valid list. Offsets are half the size of pointers, which limits an arena
+//! to 4 GiB.
+//!
+//! A node is laid out as
+//!
+//! ```text
+//! key_offset: u32 | key_size: u32 | height: u32 | tower: [u32; height] | key: [u8; key_size]
+//! ```
+//!
+//! where the tower is truncated to the node's height, as in Badger's arena
+//! skiplist. Offset 0 is the arena header and doubles as the null link.
+//!
+//! Reads are lock-free and writes take a lock, with the same publication
+//! protocol as `SkipList`. That lock only covers one process; `MmapArena`
+//! keeps a second writable mapping of the same file from being opened, and
+//! other processes attach read-only.
+
+use std::cell::UnsafeCell;
+use std::mem::{align_of, size_of};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::error::{Error, Result};
+use crate::mmap_arena::MmapArena;
+use crate::{K_BRANCHING, MAX_HEIGHT};
+
+const LIST_MAGIC: u32 = 0x4f53_4b4c;
+
+#[repr(C)]
+struct ListHeader {
+    magic: u32,
+    head: u32,
+    max_height: AtomicU32,
+    len: AtomicU32,
+}
+
+#[repr(C)]
+struct Node {
+    key_offset: u32,
+    key_size: u32,
+    height: u32,
+    tower: [AtomicU32; MAX_HEIGHT],
+}
+
+impl Node {
+    fn size(height: usize) -> usize {
+        size_of::<Node>() - (MAX_HEIGHT - height) * size_of::<AtomicU32>()
+    }
+}
+
+pub struct OffsetSkipList {
+    arena: UnsafeCell<MmapArena>,
+    // Readers resolve offsets against a copy of the base address so they
+    // never touch the arena while a writer allocates from it.
+    base: *mut u8,
+    header: u32,
+    write_lock: Mutex<StdRng>,
+}
+
+unsafe impl Send for OffsetSkipList {}
+unsafe impl Sync for OffsetSkipList {}
+
+impl OffsetSkipList {
+    /// Builds an empty list in `arena` and records it as the arena's root.
+    pub fn create(mut arena: MmapArena) -> Self {
+        assert!(arena.capacity() <= u32::MAX as usize, "offset skip lists are limited to 4 GiB");
+        let header = arena.allocate_aligned(size_of::<ListHeader>());
+        let head = arena.allocate_aligned(Node::size(MAX_HEIGHT));
+        let header_offset = arena.offset_of(header) as u32;
+        let head_offset = arena.offset_of(head) as u32;
+        unsafe {
+            std::ptr::write_bytes(head, 0, Node::size(MAX_HEIGHT));
+            (*(head as *mut Node)).height = MAX_HEIGHT as u32;
+            std::ptr::write(header as *mut ListHeader, ListHeader {
+                magic: LIST_MAGIC,
+                head: head_offset,
+                max_height: AtomicU32::new(1),
+                len: AtomicU32::new(0),
+            });
+        }
+        arena.set_root(header_offset as usize);
+        Self::with_arena(arena, header_offset)
+    }
+
+    /// Attaches to a list that `create` built in `arena` earlier, possibly in
+    /// another process or at another address.
+    ///
+    /// Every node reachable from the head is checked, so a damaged or foreign
+    /// image fails here with `Error::Corruption` instead of being read out of
+    /// bounds later. This walks the whole list. Nodes that a writer in
+    /// another process links in after the check are trusted.
+    pub fn open(arena: MmapArena) -> Result<Self> {
+        let capacity = arena.capacity();
+        if capacity > u32::MAX as usize {
+            return Err(Error::Corruption("arena is too large for an offset skip list".into()));
+        }
+        let header_offset = arena.root();
+        let used = arena.memory_usage();
+        if header_offset == 0
+            || header_offset % align_of::<ListHeader>() != 0
+            || header_offset + size_of::<ListHeader>() > used
+        {
+            return Err(Error::Corruption("arena does not hold a skip list".into()));
+        }
+        let list = Self::with_arena(arena, header_offset as u32);
+        let header = list.header();
+        if header.magic != LIST_MAGIC {
+            return Err(Error::Corruption("bad skip list magic".into()));
+        }
+        let head = header.head as usize;
+        let max_height = header.max_height.load(Ordering::Relaxed) as usize;
+        if head % align_of::<Node>() != 0
+            || head + Node::size(MAX_HEIGHT) > used
+            || !(1..=MAX_HEIGHT).contains(&max_height)
+            || unsafe { (*(list.base.add(head) as *const Node)).height } != MAX_HEIGHT as u32
+        {
+            return Err(Error::Corruption("bad skip list header".into()));
+        }
+        list.validate(list.memory_usage())?;
+        Ok(list)
+    }
+
+    fn with_arena(arena: MmapArena, header: u32) -> Self {
+        let base = arena.get(0);
+        OffsetSkipList {
+            arena: UnsafeCell::new(arena),
+            base,
+            header,
+            write_lock: Mutex::new(StdRng::seed_from_u64(0xdeadbeef)),
+        }
+    }
+
+    /// Inserts `key`, which must not already be in the list.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key is present or the arena is full or read-only.
+    pub fn insert(&self, key: &[u8]) {
+        let mut rnd = self.write_lock.lock().unwrap();
+        let mut prev = [0u32; MAX_HEIGHT];
+        let x = self.find_greater_or_equal(key, Some(&mut prev));
+        assert!(x == 0 || self.key(x) != key);
+
+        let mut height = 1;
+        while height < MAX_HEIGHT && rnd.gen_range(0..K_BRANCHING) == 0 {
+            height += 1;
+        }
+        // Allocate before touching the list, so that a full or read-only
+        // arena panics without leaving anything half-written.
+        let node = self.new_node(key, height);
+        let max_height = self.max_height();
+        if height > max_height {
+            for p in prev.iter_mut().take(height).skip(max_height) {
+                *p = self.header().head;
+            }
+            self.header().max_height.store(height as u32, Ordering::Relaxed);
+        }
+
+        for (i, &p) in prev.iter().enumerate().take(height) {
+            self.tower(node, i).store(self.tower(p, i).load(Ordering::Relaxed), Ordering::Relaxed);
+            self.tower(p, i).store(node, Ordering::Release);
+        }
+        self.header().len.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn contains(&self, key: &[u8]) -> bool {
+        let x = self.find_greater_or_equal(key, None);
+        x != 0 && self.key(x) == key
+    }
+
+    pub fn len(&self) -> usize {
+        self.header().len.load(Ordering::Relaxed) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> OffsetSkipListIterator<'_> {
+        OffsetSkipListIterator { list: self, node: 0 }
+    }
+
+    /// Bytes of the arena in use, including the headers. Waits for a
+    /// concurrent insert to finish.
+    pub fn memory_usage(&self) -> usize {
+        let _guard = self.write_lock.lock().unwrap();
+        unsafe { (*self.arena.get()).memory_usage() }
+    }
+
+    /// Writes the list to its backing file, if there is one.
+    pub fn flush(&self) -> Result<()> {
+        let _guard = self.write_lock.lock().unwrap();
+        unsafe { (*self.arena.get()).flush() }
+    }
+
+    fn new_node(&self, key: &[u8], height: usize) -> u32 {
+        let size = Node::size(height);
+        let arena = unsafe { &mut *self.arena.get() };
+        let ptr = arena.allocate_aligned(size + key.len());
+        let offset = arena.offset_of(ptr) as u32;
+        unsafe {
+            std::ptr::write_bytes(ptr, 0, size);
+            let node = ptr as *mut Node;
+            (*node).key_offset = offset + size as u32;
+            (*node).key_size = key.len() as u32;
+            (*node).height = height as u32;
+            std::ptr::copy_nonoverlapping(key.as_ptr(), ptr.add(size), key.len());
+        }
+        offset
+    }
+
+    /// Walks every level and checks that each node lies within the first
+    /// `limit` bytes, is tall enough to be linked on that level, and holds a
+    /// key greater than the one before it. Increasing keys also rule out
+    /// cycles.
+    fn validate(&self, limit: usize) -> Result<()> {
+        for level in 0..MAX_HEIGHT {
+            let mut x = self.header().head;
+            let mut last: Option<&[u8]> = None;
+            loop {
+                let next = self.next(x, level);
+                if next == 0 {
+                    break;
+                }
+                self.check_node(next, level, limit)?;
+                let key = self.key(next);
+                if last.map_or(false, |l| l >= key) {
+                    return Err(Error::Corruption(format!("skip list keys out of order at offset {}", next)));
+                }
+                last = Some(key);
+                x = next;
+            }
+        }
+        Ok(())
+    }
+
+    fn check_node(&self, node: u32, level: usize, limit: usize) -> Result<()> {
+        let offset = node as usize;
+        let bad = || Error::Corruption(format!("bad skip list node at offset {}", offset));
+        if offset % align_of::<Node>() != 0 || offset + Node::size(1) > limit {
+            return Err(bad());
+        }
+        let (height, key_offset, key_size) = unsafe {
+            let node = self.base.add(offset) as *const Node;
+            ((*node).height as usize, (*node).key_offset as usize, (*node).key_size as usize)
+        };
+        if height <= level || height > MAX_HEIGHT || offset + Node::size(height) > limit {
+            return Err(bad());
+        }
+        if key_offset + key_size > limit {
+            return Err(bad());
+        }
+        Ok(())
+    }
+
+    fn header(&self) -> &ListHeader {
+        unsafe { &*(self.base.add(self.header as usize) as *const ListHeader) }
+    }
+
+    fn max_height(&self) -> usize {
+        self.header().max_height.load(Ordering::Relaxed) as usize
+    }
+
+    fn tower(&self, node: u32, level: usize) -> &AtomicU32 {
+        unsafe {
+            let node = self.base.add(node as usize) as *const Node;
+            debug_assert!(level < (*node).height as usize);
+            &*std::ptr::addr_of!((*node).tower).cast::<AtomicU32>().add(level)
+        }
+    }
+
+    fn next(&self, node: u32, level: usize) -> u32 {
+        self.tower(node, level).load(Ordering::Acquire)
+    }
+
+    fn key(&self, node: u32) -> &[u8] {
+        // Read the fields through the raw pointer: a `&Node` would cover the
+        // full tower, which runs past the end of short nodes.
+        unsafe {
+            let node = self.base.add(node as usize) as *const Node;
+            std::slice::from_raw_parts(self.base.add((*node).key_offset as usize), (*node).key_size as usize)
+        }
+    }
+
+    fn find_greater_or_equal(&self, key: &[u8], mut prev: Option<&mut [u32; MAX_HEIGHT]>) -> u32 {
+        let mut x = self.header().head;
+        let mut level = self.max_height() - 1;
+        loop {
+            let next = self.next(x, level);
+            if next != 0 && self.key(next) < key {
+                x = next;
+            } else {
+                if let Some(prev) = prev.as_deref_mut() {
+                    prev[level] = x;
+                }
+                if level == 0 {
+                    return next;
+                }
+                level -= 1;
+            }
+        }
+    }
+
+    fn find_less_than(&self, key: &[u8]) -> u32 {
+        let mut x = self.header().head;
+        let mut level = self.max_height() - 1;
+        loop {
+            let next = self.next(x, level);
+            if next == 0 || self.key(next) >= key {
+                if level == 0 {
+                    return x;
+                }
+                level -= 1;
+            } else {
+                x = next;
+            }
+        }
+    }
+
+    fn find_last(&self) -> u32 {
+        let mut x = self.header().head;
+        let mut level = self.max_height() - 1;
+        loop {
+            let next = self.next(x, level);
+            if next == 0 {
+                if level == 0 {
+                    return x;
+                }
+                level -= 1;
+            } else {
+                x = next;
+            }
+        }
+    }
+}
+
+pub struct OffsetSkipListIterator<'a> {
+    list: &'a OffsetSkipList,
+    node: u32,
+}
+
+impl<'a> OffsetSkipListIterator<'a> {
+    pub fn valid(&self) -> bool {
+        self.node != 0
+    }
+
+    pub fn key(&self) -> &'a [u8] {
+        assert!(self.valid());
+        self.list.key(self.node)
+    }
+
+    pub fn next(&mut self) {
+        assert!(self.valid());
+        self.node = self.list.next(self.node, 0);
+    }
+
+    pub fn prev(&mut self) {
+        assert!(self.valid());
+        self.node = self.list.find_less_than(self.key());
+        if self.node == self.list.header().head {
+            self.node = 0;
+        }
+    }
+
+    pub fn seek(&mut self, target: &[u8]) {
+        self.node = self.list.find_greater_or_equal(target, None);
+    }
+
+    pub fn seek_to_first(&mut self) {
+        self.node = self.list.next(self.list.header().head, 0);
+    }
+
+    pub fn seek_to_last(&mut self) {
+        self.node = self.list.find_last();
+        if self.node == self.list.header().head {
+            self.node = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::fs;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use rand::prelude::StdRng;
+    use rand::{Rng, SeedableRng};
+    use crate::error::Error;
+    use crate::mmap_arena::MmapArena;
+    use crate::test_util::{keys, TempPath};
+    use super::{Node, OffsetSkipList};
+
+    #[test]
+    fn test_empty() {
+        let list = OffsetSkipList::create(MmapArena::anonymous(4096).unwrap());
+        assert!(list.is_empty());
+        assert!(!list.contains(b"a"));
+        let mut iter = list.iter();
+        iter.seek_to_first();
+        assert!(!iter.valid());
+        iter.seek(b"a");
+        assert!(!iter.valid());
+        iter.seek_to_last();
+        assert!(!iter.valid());
+    }
+
+    #[test]
+    fn insert_and_lookup() {
+        let mut rng = StdRng::seed_from_u64(301);
+        let list = OffsetSkipList::create(MmapArena::anonymous(1 << 20).unwrap());
+        let mut model = BTreeSet::new();
+        for _ in 0..2000 {
+            let key = rng.gen_range(0..5000u32).to_be_bytes().to_vec();
+            if model.insert(key.clone()) {
+                list.insert(&key);
+            }
+        }
+        list.insert(b"");
+        model.insert(Vec::new());
+
+        assert_eq!(list.len(), model.len());
+        assert_eq!(keys(list.iter()), model.iter().cloned().collect::<Vec<_>>());
+        for i in 0..5000u32 {
+            let key = i.to_be_bytes();
+            assert_eq!(list.contains(&key), model.contains(&key[..]));
+
+            let mut iter = list.iter();
+            iter.seek(&key);
+            match model.range(key.to_vec()..).next() {
+                Some(k) => assert_eq!(iter.key(), &k[..]),
+                None => assert!(!iter.valid()),
+            }
+        }
+
+        let mut iter = list.iter();
+        iter.seek_to_last();
+        for k in model.iter().rev() {
+            assert_eq!(iter.key(), &k[..]);
+            iter.prev();
+        }
+        assert!(!iter.valid());
+    }
+
+    #[test]
+    fn towers_are_truncated() {
+        assert_eq!(Node::size(1), 16);
+        let list = OffsetSkipList::create(MmapArena::anonymous(1 << 20).unwrap());
+        let before = list.memory_usage();
+        for i in 0..1000u32 {
+            list.insert(&i.to_be_bytes());
+        }
+        // Most nodes have a height of one, so the average node stays far
+        // below a full tower of twelve links.
+        assert!((list.memory_usage() - before) / 1000 < Node::size(4) + 4);
+    }
+
+    #[test]
+    fn position_independent() {
+        let path = TempPath::new("image");
+        let copy = TempPath::new("copy");
+        {
+            let list = OffsetSkipList::create(MmapArena::create(&path.0, 1 << 20).unwrap());
+            for i in (0..1000u32).rev() {
+                list.insert(format!("key{:04}", i).as_bytes());
+            }
+            list.flush().unwrap();
+        }
+        fs::copy(&path.0, &copy.0).unwrap();
+
+        // Map the image twice at different addresses, and a copy of it. Only
+        // one mapping of a file may be writable.
+        let a = OffsetSkipList::open(MmapArena::open(&path.0).unwrap()).unwrap();
+        assert!(matches!(MmapArena::open(&path.0), Err(Error::Io(_))));
+        let b = OffsetSkipList::open(MmapArena::open_read_only(&path.0).unwrap()).unwrap();
+        let c = OffsetSkipList::open(MmapArena::open(&copy.0).unwrap()).unwrap();
+        let expected: Vec<_> = (0..1000).map(|i| format!("key{:04}", i).into_bytes()).collect();
+        for list in [&a, &b, &c] {
+            assert_eq!(list.len(), 1000);
+            assert_eq!(keys(list.iter()), expected);
+        }
+
+        // Inserts through one mapping are visible through the other.
+        a.insert(b"key9999");
+        assert!(b.contains(b"key9999"));
+        assert!(!c.contains(b"key9999"));
+    }
+
+    #[test]
+    fn open_rejects_foreign_arena() {
+        let arena = MmapArena::anonymous(4096).unwrap();
+        assert!(matches!(OffsetSkipList::open(arena), Err(Error::Corruption(_))));
+
+        let mut arena = MmapArena::anonymous(4096).unwrap();
+        let p = arena.allocate_aligned(64);
+        arena.set_root(arena.offset_of(p));
+        assert!(matches!(OffsetSkipList::open(arena), Err(Error::Corruption(_))));
+    }
+
+    #[test]
+    fn open_rejects_bad_nodes() {
+        let path = TempPath::new("bad-nodes");
+        {
+            let list = OffsetSkipList::create(MmapArena::create(&path.0, 1 << 16).unwrap());
+            for i in 0..100u32 {
+                list.insert(&i.to_be_bytes());
+            }
+            list.flush().unwrap();
+        }
+        let image = fs::read(&path.0).unwrap();
+        let read_u32 = |at: usize| u32::from_le_bytes(image[at..at + 4].try_into().unwrap()) as usize;
+        // The arena header keeps the list header's offset at byte 32, and the
+        // list header keeps the head's offset after its magic number.
+        let head = read_u32(read_u32(32) + 4);
+        let first = read_u32(head + 12);
+        let second = read_u32(first + 12);
+
+        let corruptions: [(usize, u32); 7] = [
+            // A link past the end of the arena, and one that is misaligned.
+            (head + 12, 1 << 16),
+            (head + 12, first as u32 + 1),
+            // A key that runs past the end of the arena, and one that stays
+            // inside it but runs past the allocated bytes.
+            (first + 4, u32::MAX / 2),
+            (first + 4, (1 << 16) - read_u32(first) as u32 - 8),
+            // A node too short to be linked on level 0.
+            (first + 8, 0),
+            // A node taller than any tower.
+            (first + 8, 100),
+            // Two nodes with the same key.
+            (first, read_u32(second) as u32),
+        ];
+        for (at, value) in corruptions {
+            let mut data = image.clone();
+            data[at..at + 4].copy_from_slice(&value.to_le_bytes());
+            fs::write(&path.0, &data).unwrap();
+            let result = OffsetSkipList::open(MmapArena::open_read_only(&path.0).unwrap());
+            assert!(matches!(result, Err(Error::Corruption(_))), "{} = {}", at, value);
+        }
+        fs::write(&path.0, &image).unwrap();
+        assert_eq!(OffsetSkipList::open(MmapArena::open_read_only(&path.0).unwrap()).unwrap().len(), 100);
+    }
+
+    #[test]
+    fn concurrent_read_write() {
+        let list = OffsetSkipList::create(MmapArena::anonymous(1 << 22).unwrap());
+        let done = AtomicBool::new(false);
+        thread::scope(|s| {
+            for _ in 0..3 {
+                s.spawn(|| {
+                    while !done.load(Ordering::Acquire) {
+                        let mut iter = list.iter();
+                        iter.seek_to_first();
+                        let mut last: Option<&[u8]> = None;
+                        while iter.valid() {
+                            let key = iter.key();
+                            assert_eq!(key.len(), 8);
+                            assert!(last.map_or(true, |l| l < key));
+                            last = Some(key);
+                            iter.next();
+                        }
+                    }
+                });
+            }
+            let mut rng = StdRng::seed_from_u64(7);
+            for _ in 0..20000 {
+                let key = rng.gen::<u64>().to_be_bytes();
+                if !list.contains(&key) {
+                    list.insert(&key);
+                }
+            }
+            done.store(true, Ordering::Release);
+        });
+    }
+}
@@ -0,0 +1,173 @@
+//! Binary snapshot format for `SkipList::write_to` and `SkipList::read_from`.
+//!
+//! All integers are little-endian.
+//!
+//! ```text
+//! header:  magic: [u8; 4] = "SKPL" | version: fixed32 = 1
+//! entries: key length: varint32 | key bytes (KeyCodec::encode), in key order
+//! footer:  entry count: fixed64 | masked crc32c of everything before it: fixed32
+//! ```
+//!
+//! The count lives in the footer so that a list can be written while other
+//! threads keep inserting; the snapshot holds whatever the iterator saw.
+
+use std::fmt::Debug;
+use std::io::{Read, Write};
+
+use crate::arena::{Arena, BlockAllocator};
+use crate::codec::KeyCodec;
+use crate::coding::{decode_fixed32, decode_fixed64, get_length_prefixed_slice, put_fixed32, put_fixed64,
+                    put_length_prefixed_slice};
+use crate::crc32c;
+use crate::error::{Error, Result};
+use crate::SkipList;
+
+const MAGIC: &[u8; 4] = b"SKPL";
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 8;
+const FOOTER_SIZE: usize = 12;
+
+impl<K: Ord + Debug + Default + KeyCodec, A: BlockAllocator> SkipList<K, A> {
+    /// Writes every key of the list to `w` in the snapshot format.
+    pub fn write_to<W: Write>(&self, mut w: W) -> Result<()> {
+        let mut buf = Vec::with_capacity(HEADER_SIZE);
+        buf.extend_from_slice(MAGIC);
+        put_fixed32(&mut buf, VERSION);
+        w.write_all(&buf)?;
+        let mut crc = crc32c::value(&buf);
+
+        let mut key = Vec::new();
+        let mut count = 0u64;
+        let mut iter = self.iter();
+        iter.seek_to_first();
+        while iter.valid() {
+            key.clear();
+            iter.key().encode(&mut key);
+            buf.clear();
+            put_length_prefixed_slice(&mut buf, &key);
+            w.write_all(&buf)?;
+            crc = crc32c::extend(crc, &buf);
+            count += 1;
+            iter.next();
+        }
+
+        buf.clear();
+        put_fixed64(&mut buf, count);
+        crc = crc32c::extend(crc, &buf);
+        put_fixed32(&mut buf, crc32c::mask(crc));
+        w.write_all(&buf)?;
+        w.flush()?;
+        Ok(())
+    }
+
+    /// Reads a snapshot written by `write_to` and rebuilds the list with the
+    /// sorted bulk-load path.
+    pub fn read_from<R: Read>(arena: Arena<A>, mut r: R) -> Result<Self> {
+        let mut data = Vec::new();
+        r.read_to_end(&mut data)?;
+        if data.len() < HEADER_SIZE + FOOTER_SIZE {
+            return Err(Error::Corruption("snapshot is too short".into()));
+        }
+        if &data[..4] != MAGIC {
+            return Err(Error::Corruption("bad snapshot magic".into()));
+        }
+        let version = decode_fixed32(&data[4..]);
+        if version != VERSION {
+            return Err(Error::Corruption(format!("unsupported snapshot version {}", version)));
+        }
+
+        let crc_offset = data.len() - 4;
+        let expected = crc32c::unmask(decode_fixed32(&data[crc_offset..]));
+        if crc32c::value(&data[..crc_offset]) != expected {
+            return Err(Error::Corruption("snapshot checksum mismatch".into()));
+        }
+        let count = decode_fixed64(&data[crc_offset - 8..]);
+
+        let mut entries = &data[HEADER_SIZE..crc_offset - 8];
+        let mut decoded = 0u64;
+        let mut error = None;
+        let keys = std::iter::from_fn(|| {
+            if entries.is_empty() {
+                return None;
+            }
+            let key = get_length_prefixed_slice(&mut entries).and_then(K::decode);
+            if key.is_none() {
+                error = Some(Error::Corruption(format!("bad key at entry {}", decoded)));
+            }
+            decoded += 1;
+            key
+        });
+        let list = SkipList::from_sorted_iter(arena, keys)?;
+        if let Some(e) = error {
+            return Err(e);
+        }
+        if decoded != count {
+            return Err(Error::Corruption(format!("expected {} keys, found {}", count, decoded)));
+        }
+        Ok(list)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arena::Arena;
+    use crate::error::Error;
+    use crate::test_util::keys;
+    use crate::SkipList;
+
+    #[test]
+    fn round_trip() {
+        let list = SkipList::new(Arena::new());
+        for i in (0..10000u64).rev() {
+            list.insert(i * 3);
+        }
+        let mut buf = Vec::new();
+        list.write_to(&mut buf).unwrap();
+
+        let loaded = SkipList::<u64>::read_from(Arena::new(), &buf[..]).unwrap();
+        assert_eq!(keys(loaded.iter()), keys(list.iter()));
+
+        let list = SkipList::new(Arena::new());
+        for s in ["", "a", "ab", "b", "\u{1F980}"] {
+            list.insert(s.to_string());
+        }
+        let mut buf = Vec::new();
+        list.write_to(&mut buf).unwrap();
+        let loaded = SkipList::<String>::read_from(Arena::new(), &buf[..]).unwrap();
+        assert_eq!(keys(loaded.iter()), ["", "a", "ab", "b", "\u{1F980}"]);
+    }
+
+    #[test]
+    fn empty() {
+        let mut buf = Vec::new();
+        SkipList::<u32>::new(Arena::new()).write_to(&mut buf).unwrap();
+        assert_eq!(buf.len(), 20);
+        let loaded = SkipList::<u32>::read_from(Arena::new(), &buf[..]).unwrap();
+        assert!(keys(loaded.iter()).is_empty());
+    }
+
+    #[test]
+    fn corruption() {
+        let list = SkipList::new(Arena::new());
+        for i in 0..100u32 {
+            list.insert(i);
+        }
+        let mut buf = Vec::new();
+        list.write_to(&mut buf).unwrap();
+
+        for i in 0..buf.len() {
+            let mut corrupted = buf.clone();
+            corrupted[i] ^= 0x1;
+            assert!(matches!(SkipList::<u32>::read_from(Arena::new(), &corrupted[..]), Err(Error::Corruption(_))));
+        }
+        for len in 0..buf.len() {
+            assert!(matches!(SkipList::<u32>::read_from(Arena::new(), &buf[..len]), Err(Error::Corruption(_))));
+        }
+
+        // A well-formed snapshot whose keys have the wrong width for the type.
+        assert!(matches!(SkipList::<u64>::read_from(Arena::new(), &buf[..]), Err(Error::Corruption(_))));
+
+        // An arena too small for the head node.
+        assert!(matches!(SkipList::<u32>::read_from(Arena::with_limit(64), &buf[..]), Err(Error::ArenaFull)));
+    }
+}
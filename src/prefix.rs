@@ -0,0 +1,126 @@
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+use crate::Node;
+
+/// Decides which keys belong to a prefix.
+///
+/// `compare_prefix` must be consistent with the key order: all keys that
+/// compare `Equal` to a prefix have to be adjacent in the list, keys before
+/// them compare `Less` and keys after them compare `Greater`.
+pub trait PrefixExtractor<K> {
+    type Prefix: ?Sized;
+
+    /// Compares the prefix of `key` with `prefix`.
+    fn compare_prefix(&self, key: &K, prefix: &Self::Prefix) -> Ordering;
+}
+
+/// Byte-wise prefix for keys ordered by their byte representation, such as
+/// `Vec<u8>` and `String`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BytePrefix;
+
+impl<K: AsRef<[u8]>> PrefixExtractor<K> for BytePrefix {
+    type Prefix = [u8];
+
+    fn compare_prefix(&self, key: &K, prefix: &[u8]) -> Ordering {
+        let key = key.as_ref();
+        let n = key.len().min(prefix.len());
+        match key[..n].cmp(&prefix[..n]) {
+            Ordering::Equal if key.len() < prefix.len() => Ordering::Less,
+            ordering => ordering,
+        }
+    }
+}
+
+/// Iterates over the keys of a list that start with a given prefix, in order.
+pub struct PrefixIterator<'a, K, E: PrefixExtractor<K>> {
+    node: *mut Node<K>,
+    extractor: E,
+    prefix: &'a E::Prefix,
+    _list: PhantomData<&'a K>,
+}
+
+impl<'a, K, E: PrefixExtractor<K>> PrefixIterator<'a, K, E> {
+    pub(crate) fn new(node: *mut Node<K>, extractor: E, prefix: &'a E::Prefix) -> Self {
+        PrefixIterator { node, extractor, prefix, _list: PhantomData }
+    }
+}
+
+impl<'a, K: Ord + Debug + Default, E: PrefixExtractor<K>> Iterator for PrefixIterator<'a, K, E> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        let node = unsafe { self.node.as_ref()? };
+        if self.extractor.compare_prefix(&node.key, self.prefix) != Ordering::Equal {
+            self.node = std::ptr::null_mut();
+            return None;
+        }
+        self.node = node.next(0);
+        Some(&node.key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+    use crate::arena::Arena;
+    use crate::SkipList;
+    use super::{BytePrefix, PrefixExtractor};
+
+    #[test]
+    fn byte_prefix() {
+        let list = SkipList::new(Arena::new());
+        for k in ["a", "tenant1/", "tenant1/a", "tenant1/b", "tenant10/a", "tenant2/a", "tenant2/b"] {
+            list.insert(k.as_bytes().to_vec());
+        }
+
+        let keys: Vec<_> = list.prefix_iter(BytePrefix, b"tenant1/").collect();
+        assert_eq!(keys, [&b"tenant1/".to_vec(), &b"tenant1/a".to_vec(), &b"tenant1/b".to_vec()]);
+
+        assert_eq!(list.prefix_iter(BytePrefix, b"tenant1").count(), 4);
+        assert_eq!(list.prefix_iter(BytePrefix, b"tenant2/").count(), 2);
+        assert_eq!(list.prefix_iter(BytePrefix, b"tenant3/").count(), 0);
+        assert_eq!(list.prefix_iter(BytePrefix, b"").count(), 7);
+
+        let list = SkipList::new(Arena::new());
+        list.insert("ab".to_string());
+        list.insert("abc".to_string());
+        list.insert("abd".to_string());
+        let keys: Vec<_> = list.prefix_iter(BytePrefix, b"abc").collect();
+        assert_eq!(keys, ["abc"]);
+    }
+
+    #[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+    struct Key {
+        tenant: u32,
+        id: u64,
+    }
+
+    struct Tenant;
+
+    impl PrefixExtractor<Key> for Tenant {
+        type Prefix = u32;
+
+        fn compare_prefix(&self, key: &Key, prefix: &u32) -> Ordering {
+            key.tenant.cmp(prefix)
+        }
+    }
+
+    #[test]
+    fn composite_prefix() {
+        let list = SkipList::new(Arena::new());
+        for tenant in 0..10 {
+            for id in 0..100 {
+                list.insert(Key { tenant, id });
+            }
+        }
+
+        for tenant in 0..10 {
+            let ids: Vec<_> = list.prefix_iter(Tenant, &tenant).map(|k| k.id).collect();
+            assert_eq!(ids, (0..100).collect::<Vec<_>>());
+        }
+        assert_eq!(list.prefix_iter(Tenant, &10).count(), 0);
+    }
+}
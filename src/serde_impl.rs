@@ -0,0 +1,98 @@
+use std::fmt;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+use serde::de::{Error as _, SeqAccess, Visitor};
+use serde::ser::SerializeSeq;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::arena::{Arena, BlockAllocator};
+use crate::error::Error;
+use crate::SkipList;
+
+/// Serializes the keys as a sequence in ascending order.
+impl<K: Ord + Debug + Default + Serialize, A: BlockAllocator> Serialize for SkipList<K, A> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(None)?;
+        let mut iter = self.iter();
+        iter.seek_to_first();
+        while iter.valid() {
+            seq.serialize_element(iter.key())?;
+            iter.next();
+        }
+        seq.end()
+    }
+}
+
+/// Rebuilds a list from a strictly increasing sequence of keys, rejecting
+/// unsorted or duplicate keys with an error.
+impl<'de, K: Ord + Debug + Default + Deserialize<'de>> Deserialize<'de> for SkipList<K> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(SkipListVisitor(PhantomData))
+    }
+}
+
+struct SkipListVisitor<K>(PhantomData<K>);
+
+impl<'de, K: Ord + Debug + Default + Deserialize<'de>> Visitor<'de> for SkipListVisitor<K> {
+    type Value = SkipList<K>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a sequence of keys in strictly increasing order")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut error = None;
+        let keys = std::iter::from_fn(|| match seq.next_element() {
+            Ok(key) => key,
+            Err(e) => {
+                error = Some(e);
+                None
+            }
+        });
+        let list = SkipList::from_sorted_iter(Arena::new(), keys);
+        if let Some(e) = error {
+            return Err(e);
+        }
+        match list {
+            Ok(list) => Ok(list),
+            Err(Error::OutOfOrder) => Err(A::Error::custom("keys are unsorted or contain duplicates")),
+            Err(e) => Err(A::Error::custom(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arena::Arena;
+    use crate::SkipList;
+
+    #[test]
+    fn round_trip() {
+        let list = SkipList::new(Arena::new());
+        for k in ["b", "c", "a"] {
+            list.insert(k.to_string());
+        }
+        let json = serde_json::to_string(&list).unwrap();
+        assert_eq!(json, r#"["a","b","c"]"#);
+
+        let list: SkipList<String> = serde_json::from_str(&json).unwrap();
+        assert!(list.contains("a"));
+        assert!(list.contains("c"));
+        let mut iter = list.iter();
+        iter.seek_to_last();
+        assert_eq!(iter.key(), "c");
+
+        let list: SkipList<u32> = serde_json::from_str("[]").unwrap();
+        assert!(!list.iter().valid());
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        let err = serde_json::from_str::<SkipList<u32>>("[1, 3, 2]").err().unwrap();
+        assert!(err.to_string().contains("unsorted"));
+        assert!(serde_json::from_str::<SkipList<u32>>("[1, 1]").is_err());
+        assert!(serde_json::from_str::<SkipList<u32>>("[1, \"a\"]").is_err());
+        assert!(serde_json::from_str::<SkipList<u32>>("{}").is_err());
+    }
+}
@@ -0,0 +1,256 @@
+//! A skip list in POSIX shared memory, written by one process and read by
+//! any number of others.
+//!
+//! The list is an `OffsetSkipList`, so its links stay valid wherever each
+//! process maps the segment. Readers attach with a read-only mapping and use
+//! the same lock-free read path as `SkipList`: the owner publishes a node with
+//! release stores only after it is fully written, and the arena's memory is
+//! never reused while the segment exists.
+//!
+//! Only one process may write. The owner's insert lock lives in its own
+//! address space, so a second writer would race with it.
+
+use crate::error::Result;
+use crate::mmap_arena::MmapArena;
+use crate::offset::{OffsetSkipList, OffsetSkipListIterator};
+
+/// The writing side. Creates the segment and removes its name on drop;
+/// readers that are already attached keep their mappings.
+pub struct SharedSkipList {
+    list: OffsetSkipList,
+    name: String,
+}
+
+impl SharedSkipList {
+    /// Creates the shared-memory object `name` (for example `/my-index`)
+    /// holding an empty list of up to `capacity` bytes. Fails if the name is
+    /// taken.
+    pub fn create(name: &str, capacity: usize) -> Result<Self> {
+        let arena = MmapArena::create_shm(name, capacity)?;
+        Ok(SharedSkipList { list: OffsetSkipList::create(arena), name: name.to_string() })
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the key is present or the segment is full.
+    pub fn insert(&self, key: &[u8]) {
+        self.list.insert(key);
+    }
+
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.list.contains(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn iter(&self) -> OffsetSkipListIterator<'_> {
+        self.list.iter()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Drop for SharedSkipList {
+    fn drop(&mut self) {
+        let _ = MmapArena::unlink_shm(&self.name);
+    }
+}
+
+/// The reading side, attached to a segment that a `SharedSkipList` created.
+pub struct SharedSkipListReader {
+    list: OffsetSkipList,
+}
+
+impl SharedSkipListReader {
+    /// Maps the segment `name` read-only and checks that it holds a list.
+    ///
+    /// The arena header and every node the list links to are validated
+    /// before the reader is returned, so a segment that was damaged or
+    /// created by something else fails with `Error::Corruption`.
+    pub fn attach(name: &str) -> Result<Self> {
+        let arena = MmapArena::open_shm_read_only(name)?;
+        Ok(SharedSkipListReader { list: OffsetSkipList::open(arena)? })
+    }
+
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.list.contains(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn iter(&self) -> OffsetSkipListIterator<'_> {
+        self.list.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process::{Command, Stdio};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use crate::error::Error;
+    use crate::mmap_arena::MmapArena;
+    use crate::offset::OffsetSkipList;
+    use crate::test_util::keys;
+    use super::{SharedSkipList, SharedSkipListReader};
+
+    // Set for the copy of the test binary that `other_process` starts.
+    const READER_ENV: &str = "SKIPLIST_SHARED_READER";
+    const PROCESS_KEYS: u32 = 20000;
+
+    fn unique_name(name: &str) -> String {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        format!("/skiplist-{}-{}-{}", name, std::process::id(), n)
+    }
+
+    #[test]
+    fn attach() {
+        let name = unique_name("attach");
+        assert!(matches!(SharedSkipListReader::attach(&name), Err(Error::Io(_))));
+
+        let list = SharedSkipList::create(&name, 1 << 16).unwrap();
+        assert!(SharedSkipList::create(&name, 1 << 16).is_err());
+        for k in ["b", "a", "c"] {
+            list.insert(k.as_bytes());
+        }
+        let reader = SharedSkipListReader::attach(&name).unwrap();
+        assert_eq!(reader.len(), 3);
+        list.insert(b"d");
+        assert!(reader.contains(b"d"));
+        assert_eq!(reader.len(), 4);
+
+        // Dropping the owner removes the name but not attached readers.
+        drop(list);
+        assert!(matches!(SharedSkipListReader::attach(&name), Err(Error::Io(_))));
+        assert_eq!(keys(reader.iter()), [b"a", b"b", b"c", b"d"]);
+    }
+
+    #[test]
+    fn attach_validates_segment() {
+        // A segment that holds an arena but no list.
+        let name = unique_name("no-list");
+        let arena = MmapArena::create_shm(&name, 1 << 16).unwrap();
+        let result = SharedSkipListReader::attach(&name);
+        MmapArena::unlink_shm(&name).unwrap();
+        drop(arena);
+        assert!(matches!(result, Err(Error::Corruption(_))));
+
+        // A list whose first link points past the end of the segment.
+        let name = unique_name("bad-link");
+        let list = OffsetSkipList::create(MmapArena::create_shm(&name, 1 << 16).unwrap());
+        list.insert(b"a");
+        drop(list);
+        let arena = MmapArena::open_shm(&name).unwrap();
+        unsafe {
+            let head = *(arena.get(arena.root() + 4) as *const u32) as usize;
+            *(arena.get(head + 12) as *mut u32) = 1 << 20;
+        }
+        drop(arena);
+        let result = SharedSkipListReader::attach(&name);
+        MmapArena::unlink_shm(&name).unwrap();
+        assert!(matches!(result, Err(Error::Corruption(_))));
+    }
+
+    #[test]
+    fn other_process() {
+        let name = unique_name("process");
+        let list = SharedSkipList::create(&name, 1 << 22).unwrap();
+        for i in 0..PROCESS_KEYS / 2 {
+            list.insert(&i.to_be_bytes());
+        }
+        // Run `reader_process` in a copy of this test binary, which attaches
+        // while the rest of the keys are inserted.
+        let child = Command::new(env::current_exe().unwrap())
+            .args(["--exact", "shared::tests::reader_process", "--test-threads=1"])
+            .env(READER_ENV, &name)
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        for i in PROCESS_KEYS / 2..PROCESS_KEYS {
+            list.insert(&i.to_be_bytes());
+        }
+        let output = child.wait_with_output().unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{}", stdout);
+        // Guards against the filter matching no test at all.
+        assert!(stdout.contains("test result: ok. 1 passed"), "{}", stdout);
+    }
+
+    #[test]
+    fn reader_process() {
+        let Ok(name) = env::var(READER_ENV) else {
+            return;
+        };
+        let reader = SharedSkipListReader::attach(&name).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(60);
+        let mut last = PROCESS_KEYS as usize / 2;
+        loop {
+            let seen = keys(reader.iter());
+            assert!(seen.len() >= last);
+            assert!(seen.windows(2).all(|w| w[0] < w[1]));
+            last = seen.len();
+            if seen.len() == PROCESS_KEYS as usize {
+                assert_eq!(seen, (0..PROCESS_KEYS).map(|i| i.to_be_bytes().to_vec()).collect::<Vec<_>>());
+                break;
+            }
+            assert!(Instant::now() < deadline, "saw {} of {} keys", seen.len(), PROCESS_KEYS);
+        }
+        assert!(reader.contains(&(PROCESS_KEYS - 1).to_be_bytes()));
+    }
+
+    #[test]
+    fn concurrent_readers() {
+        let name = unique_name("concurrent");
+        let list = SharedSkipList::create(&name, 1 << 22).unwrap();
+        let done = AtomicBool::new(false);
+        thread::scope(|s| {
+            for _ in 0..3 {
+                // Every reader has its own read-only mapping, as a reader in
+                // another process would.
+                let reader = SharedSkipListReader::attach(&name).unwrap();
+                let done = &done;
+                s.spawn(move || {
+                    let mut seen = 0;
+                    while !done.load(Ordering::Acquire) {
+                        let mut iter = reader.iter();
+                        iter.seek_to_first();
+                        let mut count = 0;
+                        let mut last: Option<&[u8]> = None;
+                        while iter.valid() {
+                            let key = iter.key();
+                            assert!(last.map_or(true, |l| l < key));
+                            last = Some(key);
+                            count += 1;
+                            iter.next();
+                        }
+                        // Keys are never removed, so no scan sees fewer.
+                        assert!(count >= seen);
+                        seen = count;
+                    }
+                });
+            }
+            for i in 0..10000u32 {
+                list.insert(&i.wrapping_mul(2654435761).to_be_bytes());
+            }
+            done.store(true, Ordering::Release);
+        });
+        assert_eq!(SharedSkipListReader::attach(&name).unwrap().len(), 10000);
+    }
+}
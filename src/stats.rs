@@ -0,0 +1,54 @@
+//! Statistics about a skip list and the arena behind it.
+
+/// Where the memory of an arena, and of the list built in it, goes.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MemoryStats {
+    /// Bytes in the blocks the arena holds, whether handed out or not.
+    pub block_bytes: usize,
+    /// Bytes the arena spends tracking its blocks.
+    pub bookkeeping_bytes: usize,
+    /// Bytes handed out by `allocate` and `allocate_aligned`.
+    pub allocated_bytes: usize,
+    /// Bytes skipped to align allocations.
+    pub alignment_waste: usize,
+    pub blocks: usize,
+    /// Blocks that hold a single allocation too large to share a block.
+    pub large_blocks: usize,
+    /// Number of keys in the list.
+    pub len: usize,
+    /// Bytes of the nodes apart from their keys: the node in the arena, the
+    /// head included, and its tower of links on the heap.
+    pub node_bytes: usize,
+    /// Bytes of the keys stored in the nodes. Memory that a key owns
+    /// elsewhere, such as the buffer of a `String`, is not counted.
+    pub key_bytes: usize,
+    /// Approximate bytes per key spent on anything but the key itself:
+    /// node fields, links, alignment and the unused tail of blocks.
+    pub overhead_per_node: f64,
+}
+
+impl MemoryStats {
+    /// Bytes in the arena's blocks that have not been handed out.
+    pub fn unused_bytes(&self) -> usize {
+        self.block_bytes - self.allocated_bytes - self.alignment_waste
+    }
+}
+
+/// The shape of a skip list, for tuning the branching factor.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StructureStats {
+    /// Height of the tallest tower.
+    pub max_height: usize,
+    /// Number of keys.
+    pub len: usize,
+    /// `height_histogram[h - 1]` is the number of nodes with a tower of `h`
+    /// levels. With a branching factor of 4, each entry should be about a
+    /// quarter of the one before.
+    pub height_histogram: Vec<usize>,
+    /// `level_counts[i]` is the number of nodes linked at level `i`.
+    pub level_counts: Vec<usize>,
+    /// Number of keys looked up to measure `average_search_path`.
+    pub sampled_keys: usize,
+    /// Average number of nodes a lookup compares its key with.
+    pub average_search_path: f64,
+}
@@ -0,0 +1,17 @@
+//! The synchronization primitives behind `SkipList`.
+//!
+//! Building with `RUSTFLAGS="--cfg loom"` swaps them for loom's, so that the
+//! loom tests can explore every interleaving, and every outcome the memory
+//! model allows, of a writer publishing nodes while readers traverse.
+
+#[cfg(loom)]
+pub(crate) use loom::sync::atomic::{AtomicPtr, AtomicUsize};
+#[cfg(loom)]
+pub(crate) use loom::sync::Mutex;
+
+#[cfg(not(loom))]
+pub(crate) use std::sync::atomic::{AtomicPtr, AtomicUsize};
+#[cfg(not(loom))]
+pub(crate) use std::sync::Mutex;
+
+pub(crate) use std::sync::atomic::Ordering;
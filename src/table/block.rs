@@ -0,0 +1,295 @@
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+use crate::coding::{decode_fixed32, get_varint32};
+use crate::error::{Error, Result};
+
+/// The contents of a block written by `BlockBuilder`.
+#[derive(Clone)]
+pub(crate) struct Block {
+    data: Arc<Vec<u8>>,
+    restart_offset: usize,
+    num_restarts: usize,
+}
+
+impl Block {
+    pub fn new(data: Vec<u8>) -> Result<Self> {
+        if data.len() < 4 {
+            return Err(Error::Corruption("block is too short".into()));
+        }
+        let num_restarts = decode_fixed32(&data[data.len() - 4..]) as usize;
+        let max_restarts = (data.len() - 4) / 4;
+        if num_restarts == 0 || num_restarts > max_restarts {
+            return Err(Error::Corruption("bad block restart array".into()));
+        }
+        let restart_offset = data.len() - (1 + num_restarts) * 4;
+        Ok(Block { data: Arc::new(data), restart_offset, num_restarts })
+    }
+
+    pub fn iter(&self) -> BlockIter {
+        BlockIter {
+            block: self.clone(),
+            current: self.restart_offset,
+            restart_index: self.num_restarts,
+            key: Vec::new(),
+            value_offset: 0,
+            value_len: 0,
+            corrupted: false,
+        }
+    }
+}
+
+/// Iterates over the entries of a block, in the style of `SkipListIterator`.
+pub(crate) struct BlockIter {
+    block: Block,
+    // Offset of the current entry; `restart_offset` if not valid.
+    current: usize,
+    // Index of the restart block that contains `current`.
+    restart_index: usize,
+    key: Vec<u8>,
+    value_offset: usize,
+    value_len: usize,
+    corrupted: bool,
+}
+
+impl BlockIter {
+    pub fn valid(&self) -> bool {
+        self.current < self.block.restart_offset
+    }
+
+    pub fn corrupted(&self) -> bool {
+        self.corrupted
+    }
+
+    pub fn key(&self) -> &[u8] {
+        assert!(self.valid());
+        &self.key
+    }
+
+    pub fn value(&self) -> &[u8] {
+        assert!(self.valid());
+        &self.block.data[self.value_offset..self.value_offset + self.value_len]
+    }
+
+    pub fn next(&mut self) {
+        assert!(self.valid());
+        self.parse_next_key();
+    }
+
+    pub fn prev(&mut self) {
+        assert!(self.valid());
+
+        // Scan backwards to a restart point before the current entry.
+        let original = self.current;
+        while self.restart_point(self.restart_index) >= original {
+            if self.restart_index == 0 {
+                self.mark_invalid();
+                return;
+            }
+            self.restart_index -= 1;
+        }
+
+        // Walk forward to the entry just before `original`.
+        self.seek_to_restart_point(self.restart_index);
+        while self.parse_next_key() && self.next_entry_offset() < original {}
+    }
+
+    /// Positions at the first entry whose key does not compare `Less` to the
+    /// target, as reported by `cmp`. For the full keys stored at restart
+    /// points, `cmp` also receives the index of the restart point, so that
+    /// callers can cache what they derive from those keys.
+    pub fn seek(&mut self, mut cmp: impl FnMut(Option<usize>, &[u8]) -> Ordering) {
+        // Binary search for the last restart point with a key before the target.
+        let mut left = 0;
+        let mut right = self.block.num_restarts - 1;
+        while left < right {
+            let mid = (left + right + 1) / 2;
+            let key = match self.restart_key(mid) {
+                Some(key) => key,
+                None => {
+                    self.corruption();
+                    return;
+                }
+            };
+            if cmp(Some(mid), key) == Ordering::Less {
+                left = mid;
+            } else {
+                right = mid - 1;
+            }
+        }
+
+        self.seek_to_restart_point(left);
+        while self.parse_next_key() {
+            let restart = Some(self.restart_index).filter(|&i| self.restart_point(i) == self.current);
+            if cmp(restart, &self.key) != Ordering::Less {
+                return;
+            }
+        }
+    }
+
+    pub fn seek_to_first(&mut self) {
+        self.seek_to_restart_point(0);
+        self.parse_next_key();
+    }
+
+    pub fn seek_to_last(&mut self) {
+        self.seek_to_restart_point(self.block.num_restarts - 1);
+        while self.parse_next_key() && self.next_entry_offset() < self.block.restart_offset {}
+    }
+
+    fn next_entry_offset(&self) -> usize {
+        self.value_offset + self.value_len
+    }
+
+    fn restart_point(&self, index: usize) -> usize {
+        decode_fixed32(&self.block.data[self.block.restart_offset + index * 4..]) as usize
+    }
+
+    fn seek_to_restart_point(&mut self, index: usize) {
+        self.key.clear();
+        self.restart_index = index;
+        // `parse_next_key` starts at the end of the current value.
+        self.value_offset = self.restart_point(index);
+        self.value_len = 0;
+    }
+
+    /// Returns the full key stored at a restart point.
+    fn restart_key(&self, index: usize) -> Option<&[u8]> {
+        let offset = self.restart_point(index);
+        let (shared, non_shared, _, key_offset) = self.decode_entry(offset)?;
+        if shared != 0 {
+            return None;
+        }
+        Some(&self.block.data[key_offset..key_offset + non_shared])
+    }
+
+    /// Decodes an entry header, returning the shared, non-shared and value
+    /// lengths and the offset of the key delta.
+    fn decode_entry(&self, offset: usize) -> Option<(usize, usize, usize, usize)> {
+        let limit = self.block.restart_offset;
+        if offset >= limit {
+            return None;
+        }
+        let mut src = &self.block.data[offset..limit];
+        let shared = get_varint32(&mut src)? as usize;
+        let non_shared = get_varint32(&mut src)? as usize;
+        let value_len = get_varint32(&mut src)? as usize;
+        if src.len() < non_shared + value_len {
+            return None;
+        }
+        Some((shared, non_shared, value_len, limit - src.len()))
+    }
+
+    fn parse_next_key(&mut self) -> bool {
+        self.current = self.next_entry_offset();
+        if self.current >= self.block.restart_offset {
+            self.mark_invalid();
+            return false;
+        }
+
+        match self.decode_entry(self.current) {
+            Some((shared, non_shared, value_len, key_offset)) if shared <= self.key.len() => {
+                self.key.truncate(shared);
+                self.key.extend_from_slice(&self.block.data[key_offset..key_offset + non_shared]);
+                self.value_offset = key_offset + non_shared;
+                self.value_len = value_len;
+                while self.restart_index + 1 < self.block.num_restarts
+                    && self.restart_point(self.restart_index + 1) < self.current
+                {
+                    self.restart_index += 1;
+                }
+                true
+            }
+            _ => {
+                self.corruption();
+                false
+            }
+        }
+    }
+
+    fn mark_invalid(&mut self) {
+        self.current = self.block.restart_offset;
+        self.restart_index = self.block.num_restarts;
+    }
+
+    fn corruption(&mut self) {
+        self.corrupted = true;
+        self.key.clear();
+        self.mark_invalid();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Block;
+    use crate::table::block_builder::BlockBuilder;
+
+    fn build(keys: &[String], restart_interval: usize) -> Block {
+        let mut builder = BlockBuilder::new(restart_interval);
+        for key in keys {
+            builder.add(key.as_bytes(), key.to_uppercase().as_bytes());
+        }
+        Block::new(builder.finish().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_empty() {
+        let block = build(&[], 16);
+        let mut iter = block.iter();
+        assert!(!iter.valid());
+        iter.seek_to_first();
+        assert!(!iter.valid());
+        iter.seek_to_last();
+        assert!(!iter.valid());
+        iter.seek(|_, k| k.cmp(b"a"));
+        assert!(!iter.valid());
+        assert!(!iter.corrupted());
+    }
+
+    #[test]
+    fn iterate_and_seek() {
+        let keys: Vec<String> = (0..500).map(|i| format!("key{:05}", i * 2)).collect();
+        for restart_interval in [1, 2, 16, 1000] {
+            let block = build(&keys, restart_interval);
+
+            let mut iter = block.iter();
+            iter.seek_to_first();
+            for key in &keys {
+                assert_eq!(iter.key(), key.as_bytes());
+                assert_eq!(iter.value(), key.to_uppercase().as_bytes());
+                iter.next();
+            }
+            assert!(!iter.valid());
+
+            iter.seek_to_last();
+            for key in keys.iter().rev() {
+                assert_eq!(iter.key(), key.as_bytes());
+                iter.prev();
+            }
+            assert!(!iter.valid());
+
+            for i in 0..1001 {
+                let target = format!("key{:05}", i);
+                iter.seek(|_, k| k.cmp(target.as_bytes()));
+                match keys.iter().find(|k| **k >= target) {
+                    Some(key) => assert_eq!(iter.key(), key.as_bytes()),
+                    None => assert!(!iter.valid()),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn corrupted_block() {
+        assert!(Block::new(vec![]).is_err());
+        assert!(Block::new(vec![0, 0, 0, 0]).is_err());
+        assert!(Block::new(vec![0, 0, 0, 0, 2, 0, 0, 0]).is_err());
+
+        // An entry whose key delta runs past the end of the entries.
+        let block = Block::new(vec![0, 9, 0, b'a', 0, 0, 0, 0, 1, 0, 0, 0]).unwrap();
+        let mut iter = block.iter();
+        iter.seek_to_first();
+        assert!(!iter.valid());
+        assert!(iter.corrupted());
+    }
+}
@@ -0,0 +1,81 @@
+use crate::coding::{put_fixed32, put_varint32};
+
+/// Builds a block of prefix-compressed entries.
+///
+/// When a key is stored, the prefix it shares with the previous key is
+/// dropped. Every `restart_interval` keys the compression restarts and the
+/// full key is stored; the offsets of these restart points are kept at the
+/// end of the block so a reader can binary search them.
+///
+/// ```text
+/// entry:   shared: varint32 | non_shared: varint32 | value_length: varint32
+///          | key_delta: [u8; non_shared] | value: [u8; value_length]
+/// trailer: restarts: [fixed32; num_restarts] | num_restarts: fixed32
+/// ```
+pub(crate) struct BlockBuilder {
+    buffer: Vec<u8>,
+    restarts: Vec<u32>,
+    counter: usize,
+    restart_interval: usize,
+    last_key: Vec<u8>,
+}
+
+impl BlockBuilder {
+    pub fn new(restart_interval: usize) -> Self {
+        assert!(restart_interval >= 1);
+        BlockBuilder {
+            buffer: Vec::new(),
+            restarts: vec![0],
+            counter: 0,
+            restart_interval,
+            last_key: Vec::new(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.restarts.clear();
+        self.restarts.push(0);
+        self.counter = 0;
+        self.last_key.clear();
+    }
+
+    /// Keys must be added in increasing order.
+    pub fn add(&mut self, key: &[u8], value: &[u8]) {
+        let mut shared = 0;
+        if self.counter < self.restart_interval {
+            shared = self.last_key.iter().zip(key).take_while(|(a, b)| a == b).count();
+        } else {
+            self.restarts.push(self.buffer.len() as u32);
+            self.counter = 0;
+        }
+        let non_shared = key.len() - shared;
+
+        put_varint32(&mut self.buffer, shared as u32);
+        put_varint32(&mut self.buffer, non_shared as u32);
+        put_varint32(&mut self.buffer, value.len() as u32);
+        self.buffer.extend_from_slice(&key[shared..]);
+        self.buffer.extend_from_slice(value);
+
+        self.last_key.truncate(shared);
+        self.last_key.extend_from_slice(&key[shared..]);
+        self.counter += 1;
+    }
+
+    /// Appends the restart array and returns the finished block contents.
+    pub fn finish(&mut self) -> &[u8] {
+        for &restart in &self.restarts {
+            put_fixed32(&mut self.buffer, restart);
+        }
+        put_fixed32(&mut self.buffer, self.restarts.len() as u32);
+        &self.buffer
+    }
+
+    pub fn current_size_estimate(&self) -> usize {
+        self.buffer.len() + self.restarts.len() * 4 + 4
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+}
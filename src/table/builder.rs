@@ -0,0 +1,169 @@
+use std::fmt::Debug;
+use std::io::Write;
+use std::marker::PhantomData;
+
+use crate::codec::KeyCodec;
+use crate::coding::put_fixed32;
+use crate::crc32c;
+use crate::error::{Error, Result};
+use crate::table::block_builder::BlockBuilder;
+use crate::table::{encode_footer, BlockHandle, NO_COMPRESSION};
+use crate::arena::BlockAllocator;
+use crate::SkipListIterator;
+
+#[derive(Clone, Copy, Debug)]
+pub struct TableOptions {
+    /// Approximate size of the encoded keys packed into one data block.
+    pub block_size: usize,
+    /// Number of keys between restart points for prefix compression.
+    pub block_restart_interval: usize,
+}
+
+impl Default for TableOptions {
+    fn default() -> Self {
+        TableOptions { block_size: 4096, block_restart_interval: 16 }
+    }
+}
+
+/// Writes keys, in increasing order, to a table file.
+pub struct TableBuilder<K, W: Write> {
+    writer: W,
+    options: TableOptions,
+    data_block: BlockBuilder,
+    index_block: BlockBuilder,
+    offset: u64,
+    num_entries: u64,
+    last_key: Vec<u8>,
+    // `last_key` decoded, so that `add` does not decode it for every key.
+    // `None` after `add_all` until the next `add` needs it.
+    last: Option<K>,
+    _key: PhantomData<K>,
+}
+
+impl<K: Ord + KeyCodec, W: Write> TableBuilder<K, W> {
+    pub fn new(writer: W) -> Self {
+        Self::with_options(writer, TableOptions::default())
+    }
+
+    pub fn with_options(writer: W, options: TableOptions) -> Self {
+        TableBuilder {
+            writer,
+            options,
+            data_block: BlockBuilder::new(options.block_restart_interval),
+            // Index lookups binary search every entry, so there is nothing to
+            // gain from prefix compression.
+            index_block: BlockBuilder::new(1),
+            offset: 0,
+            num_entries: 0,
+            last_key: Vec::new(),
+            last: None,
+            _key: PhantomData,
+        }
+    }
+
+    /// Adds a key, which must be greater than every key added before, or
+    /// returns `Error::OutOfOrder` and leaves the table unchanged.
+    pub fn add(&mut self, key: &K) -> Result<()>
+    where
+        K: Clone,
+    {
+        self.check_order(key)?;
+        self.add_unchecked(key)?;
+        self.last = Some(key.clone());
+        Ok(())
+    }
+
+    /// Adds every key of a skip list, from the first to the last. Only the
+    /// first key is checked against the keys added before; the list keeps
+    /// the rest in order.
+    pub fn add_all<A: BlockAllocator>(&mut self, mut iter: SkipListIterator<'_, K, A>) -> Result<()>
+    where
+        K: Debug + Default,
+    {
+        iter.seek_to_first();
+        if iter.valid() {
+            self.check_order(iter.key())?;
+        }
+        while iter.valid() {
+            self.add_unchecked(iter.key())?;
+            iter.next();
+        }
+        Ok(())
+    }
+
+    fn check_order(&mut self, key: &K) -> Result<()> {
+        if self.num_entries == 0 {
+            return Ok(());
+        }
+        if self.last.is_none() {
+            self.last = K::decode(&self.last_key);
+        }
+        if self.last.as_ref().map_or(false, |last| last >= key) {
+            return Err(Error::OutOfOrder);
+        }
+        Ok(())
+    }
+
+    fn add_unchecked(&mut self, key: &K) -> Result<()> {
+        self.last = None;
+        self.last_key.clear();
+        key.encode(&mut self.last_key);
+
+        self.data_block.add(&self.last_key, &[]);
+        self.num_entries += 1;
+        if self.data_block.current_size_estimate() >= self.options.block_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn num_entries(&self) -> u64 {
+        self.num_entries
+    }
+
+    /// Size of the file written so far.
+    pub fn file_size(&self) -> u64 {
+        self.offset
+    }
+
+    /// Writes the remaining data, the index block and the footer, and returns
+    /// the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.flush()?;
+        let index_handle = {
+            let contents = self.index_block.finish().to_vec();
+            self.write_block(&contents)?
+        };
+        let footer = encode_footer(&index_handle);
+        self.writer.write_all(&footer)?;
+        self.offset += footer.len() as u64;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.data_block.is_empty() {
+            return Ok(());
+        }
+        let contents = self.data_block.finish().to_vec();
+        let handle = self.write_block(&contents)?;
+        self.data_block.reset();
+
+        let mut encoded = Vec::new();
+        handle.encode_to(&mut encoded);
+        self.index_block.add(&self.last_key, &encoded);
+        Ok(())
+    }
+
+    fn write_block(&mut self, contents: &[u8]) -> Result<BlockHandle> {
+        let handle = BlockHandle { offset: self.offset, size: contents.len() as u64 };
+        let mut trailer = vec![NO_COMPRESSION];
+        let crc = crc32c::extend(crc32c::value(contents), &trailer);
+        put_fixed32(&mut trailer, crc32c::mask(crc));
+
+        self.writer.write_all(contents)?;
+        self.writer.write_all(&trailer)?;
+        self.offset += (contents.len() + trailer.len()) as u64;
+        Ok(handle)
+    }
+}
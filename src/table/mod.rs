@@ -0,0 +1,270 @@
+//! Sorted, block-based table files, modeled on LevelDB's SSTables.
+//!
+//! A `TableBuilder` flushes the contents of a skip list to a file and a
+//! `TableReader` looks keys up in it again. Keys are encoded with `KeyCodec`
+//! and compared in their decoded form, so the file keeps the order of `K`.
+//!
+//! ```text
+//! [data block 1] [trailer] ... [data block N] [trailer]
+//! [index block] [trailer]
+//! [footer]
+//!
+//! trailer: compression type: u8 (always 0) | masked crc32c of block and type: fixed32
+//! footer:  index block handle, padded to 20 bytes | magic: fixed64
+//! handle:  offset: varint64 | size: varint64
+//! ```
+//!
+//! Data blocks hold the keys with empty values. The index block maps the
+//! last key of each data block to the handle of that block. See
+//! `BlockBuilder` for the layout of a block.
+
+mod block;
+mod block_builder;
+mod builder;
+mod reader;
+
+pub use builder::{TableBuilder, TableOptions};
+pub use reader::{TableIterator, TableReader};
+
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::coding::{decode_fixed32, decode_fixed64, get_varint64, put_fixed64, put_varint64};
+use crate::crc32c;
+use crate::error::{Error, Result};
+
+const TABLE_MAGIC: u64 = 0x736b_6970_6c69_7374;
+const BLOCK_TRAILER_SIZE: usize = 5;
+const MAX_HANDLE_LENGTH: usize = 20;
+const FOOTER_SIZE: usize = MAX_HANDLE_LENGTH + 8;
+const NO_COMPRESSION: u8 = 0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct BlockHandle {
+    offset: u64,
+    size: u64,
+}
+
+impl BlockHandle {
+    fn encode_to(&self, dst: &mut Vec<u8>) {
+        put_varint64(dst, self.offset);
+        put_varint64(dst, self.size);
+    }
+
+    fn decode_from(src: &mut &[u8]) -> Result<Self> {
+        match (get_varint64(src), get_varint64(src)) {
+            (Some(offset), Some(size)) => Ok(BlockHandle { offset, size }),
+            _ => Err(Error::Corruption("bad block handle".into())),
+        }
+    }
+}
+
+fn encode_footer(index_handle: &BlockHandle) -> Vec<u8> {
+    let mut footer = Vec::with_capacity(FOOTER_SIZE);
+    index_handle.encode_to(&mut footer);
+    footer.resize(MAX_HANDLE_LENGTH, 0);
+    put_fixed64(&mut footer, TABLE_MAGIC);
+    footer
+}
+
+fn decode_footer(footer: &[u8]) -> Result<BlockHandle> {
+    if decode_fixed64(&footer[MAX_HANDLE_LENGTH..]) != TABLE_MAGIC {
+        return Err(Error::Corruption("not a table file (bad magic number)".into()));
+    }
+    BlockHandle::decode_from(&mut &footer[..MAX_HANDLE_LENGTH])
+}
+
+/// Reads the block identified by `handle` and verifies its trailer.
+///
+/// Handles come from the footer and the index block, which the block
+/// checksums do not cover, so they are checked against `file_len` before
+/// anything is allocated.
+fn read_block<R: Read + Seek>(file: &mut R, handle: &BlockHandle, file_len: u64) -> Result<Vec<u8>> {
+    let end = handle.offset.checked_add(handle.size).and_then(|end| end.checked_add(BLOCK_TRAILER_SIZE as u64));
+    if end.map_or(true, |end| end > file_len) {
+        return Err(Error::Corruption(format!(
+            "block at offset {} of size {} runs past the end of the file", handle.offset, handle.size)));
+    }
+    let n = handle.size as usize;
+    let mut buf = vec![0; n + BLOCK_TRAILER_SIZE];
+    file.seek(SeekFrom::Start(handle.offset))?;
+    file.read_exact(&mut buf).map_err(|e| match e.kind() {
+        std::io::ErrorKind::UnexpectedEof => Error::Corruption("truncated block read".into()),
+        _ => Error::Io(e),
+    })?;
+
+    let expected = crc32c::unmask(decode_fixed32(&buf[n + 1..]));
+    if crc32c::value(&buf[..n + 1]) != expected {
+        return Err(Error::Corruption("block checksum mismatch".into()));
+    }
+    if buf[n] != NO_COMPRESSION {
+        return Err(Error::Corruption(format!("unknown block compression type {}", buf[n])));
+    }
+    buf.truncate(n);
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::io::Cursor;
+    use rand::prelude::StdRng;
+    use rand::{Rng, SeedableRng};
+    use crate::arena::Arena;
+    use crate::error::Error;
+    use crate::SkipList;
+    use super::{TableBuilder, TableOptions, TableReader};
+
+    fn build<K>(list: &SkipList<K>, options: TableOptions) -> Vec<u8>
+    where
+        K: Ord + std::fmt::Debug + Default + crate::codec::KeyCodec,
+    {
+        let mut builder = TableBuilder::with_options(Vec::new(), options);
+        builder.add_all(list.iter()).unwrap();
+        builder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_empty() {
+        let list = SkipList::<u64>::new(Arena::new());
+        let data = build(&list, TableOptions::default());
+        let table = TableReader::<u64, _>::open(Cursor::new(data)).unwrap();
+        let mut iter = table.iter();
+        assert!(!iter.valid());
+        iter.seek_to_first();
+        assert!(!iter.valid());
+        iter.seek_to_last();
+        assert!(!iter.valid());
+        iter.seek(&10);
+        assert!(!iter.valid());
+        assert!(iter.status().is_none());
+    }
+
+    #[test]
+    fn matches_model() {
+        let mut rng = StdRng::seed_from_u64(301);
+        let list = SkipList::new(Arena::new());
+        let mut keys = BTreeSet::new();
+        for _ in 0..5000 {
+            let key = format!("key{:08}", rng.gen_range(0..100000));
+            if keys.insert(key.clone()) {
+                list.insert(key);
+            }
+        }
+
+        for (block_size, block_restart_interval) in [(4096, 16), (64, 1), (256, 3), (1 << 20, 16)] {
+            let data = build(&list, TableOptions { block_size, block_restart_interval });
+            let table = TableReader::<String, _>::open(Cursor::new(data)).unwrap();
+            let mut iter = table.iter();
+
+            iter.seek_to_first();
+            for k in &keys {
+                assert!(iter.valid());
+                assert_eq!(iter.key(), k);
+                iter.next();
+            }
+            assert!(!iter.valid());
+
+            iter.seek_to_last();
+            for k in keys.iter().rev() {
+                assert!(iter.valid());
+                assert_eq!(iter.key(), k);
+                iter.prev();
+            }
+            assert!(!iter.valid());
+
+            for _ in 0..1000 {
+                let target = format!("key{:08}", rng.gen_range(0..100010));
+                iter.seek(target.as_str());
+                let mut model = keys.range(target.clone()..);
+                for _ in 0..3 {
+                    match model.next() {
+                        Some(k) => {
+                            assert_eq!(iter.key(), k);
+                            iter.next();
+                        }
+                        None => {
+                            assert!(!iter.valid());
+                            break;
+                        }
+                    }
+                }
+            }
+            assert!(iter.status().is_none());
+        }
+    }
+
+    #[test]
+    fn integer_keys() {
+        let list = SkipList::from_sorted_iter(Arena::new(), -1000i64..1000).unwrap();
+        let data = build(&list, TableOptions { block_size: 128, block_restart_interval: 4 });
+        let table = TableReader::<i64, _>::open(Cursor::new(data)).unwrap();
+        let mut iter = table.iter();
+        iter.seek(&-1);
+        assert_eq!(*iter.key(), -1);
+        iter.prev();
+        assert_eq!(*iter.key(), -2);
+        iter.seek_to_first();
+        assert_eq!(*iter.key(), -1000);
+        iter.seek_to_last();
+        assert_eq!(*iter.key(), 999);
+    }
+
+    #[test]
+    fn out_of_order() {
+        let mut builder = TableBuilder::new(Vec::new());
+        builder.add(&"b".to_string()).unwrap();
+        assert!(matches!(builder.add(&"b".to_string()), Err(Error::OutOfOrder)));
+        assert!(matches!(builder.add(&"a".to_string()), Err(Error::OutOfOrder)));
+        builder.add(&"c".to_string()).unwrap();
+        assert_eq!(builder.num_entries(), 2);
+
+        // A list is checked against the keys added before it, and the keys
+        // after it against its last one.
+        let before = SkipList::from_sorted_iter(Arena::new(), ["a".to_string(), "d".to_string()]).unwrap();
+        assert!(matches!(builder.add_all(before.iter()), Err(Error::OutOfOrder)));
+        let after = SkipList::from_sorted_iter(Arena::new(), ["d".to_string(), "e".to_string()]).unwrap();
+        builder.add_all(after.iter()).unwrap();
+        assert!(matches!(builder.add(&"e".to_string()), Err(Error::OutOfOrder)));
+        builder.add(&"f".to_string()).unwrap();
+        assert_eq!(builder.num_entries(), 5);
+
+        let table = TableReader::<String, _>::open(Cursor::new(builder.finish().unwrap())).unwrap();
+        let mut iter = table.iter();
+        iter.seek("a");
+        for key in ["b", "c", "d", "e", "f"] {
+            assert_eq!(iter.key(), key);
+            iter.next();
+        }
+        assert!(!iter.valid());
+    }
+
+    #[test]
+    fn corruption() {
+        let list = SkipList::from_sorted_iter(Arena::new(), 0u32..1000).unwrap();
+        let data = build(&list, TableOptions { block_size: 256, block_restart_interval: 16 });
+
+        let mut bad_magic = data.clone();
+        *bad_magic.last_mut().unwrap() ^= 1;
+        assert!(matches!(TableReader::<u32, _>::open(Cursor::new(bad_magic)), Err(Error::Corruption(_))));
+        assert!(matches!(TableReader::<u32, _>::open(Cursor::new(&data[..10])), Err(Error::Corruption(_))));
+
+        // Index handles that point past the end of the file, or overflow.
+        for (offset, size) in [(0, data.len() as u64), (u64::MAX - 2, 2), (0, u64::MAX)] {
+            let mut bad_handle = data.clone();
+            let footer = super::encode_footer(&super::BlockHandle { offset, size });
+            let at = bad_handle.len() - footer.len();
+            bad_handle[at..].copy_from_slice(&footer);
+            assert!(matches!(TableReader::<u32, _>::open(Cursor::new(bad_handle)), Err(Error::Corruption(_))));
+        }
+
+        // A flipped bit in the first data block is reported by the iterator.
+        let mut bad_block = data.clone();
+        bad_block[10] ^= 1;
+        let table = TableReader::<u32, _>::open(Cursor::new(bad_block)).unwrap();
+        let mut iter = table.iter();
+        iter.seek_to_first();
+        assert!(matches!(iter.status(), Some(Error::Corruption(_))));
+        assert!(iter.valid());
+        assert!(*iter.key() > 0);
+    }
+}
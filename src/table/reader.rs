@@ -0,0 +1,254 @@
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::io::{Read, Seek, SeekFrom};
+use std::marker::PhantomData;
+use std::sync::Mutex;
+
+use crate::codec::KeyCodec;
+use crate::error::{Error, Result};
+use crate::table::block::{Block, BlockIter};
+use crate::table::{decode_footer, read_block, BlockHandle, FOOTER_SIZE};
+
+/// An open table file. Data blocks are read on demand by its iterators.
+pub struct TableReader<K, R> {
+    file: Mutex<R>,
+    file_len: u64,
+    index: Block,
+    _key: PhantomData<K>,
+}
+
+impl<K: Ord + KeyCodec, R: Read + Seek> TableReader<K, R> {
+    /// Reads the footer and index block of a table written by `TableBuilder`.
+    pub fn open(mut file: R) -> Result<Self> {
+        let size = file.seek(SeekFrom::End(0))?;
+        if size < FOOTER_SIZE as u64 {
+            return Err(Error::Corruption("file is too short to be a table".into()));
+        }
+        let mut footer = [0; FOOTER_SIZE];
+        file.seek(SeekFrom::Start(size - FOOTER_SIZE as u64))?;
+        file.read_exact(&mut footer)?;
+        let index_handle = decode_footer(&footer)?;
+        let index = Block::new(read_block(&mut file, &index_handle, size)?)?;
+        Ok(TableReader { file: Mutex::new(file), file_len: size, index, _key: PhantomData })
+    }
+
+    pub fn iter(&self) -> TableIterator<'_, K, R> {
+        TableIterator {
+            table: self,
+            index: self.index.iter(),
+            data: None,
+            data_handle: None,
+            index_restarts: RestartKeys::default(),
+            data_restarts: RestartKeys::default(),
+            key: None,
+            status: None,
+        }
+    }
+
+    fn read_block(&self, handle: &BlockHandle) -> Result<Block> {
+        let mut file = self.file.lock().unwrap();
+        Block::new(read_block(&mut *file, handle, self.file_len)?)
+    }
+}
+
+/// Iterates over the keys of a table with the same interface as
+/// `SkipListIterator`.
+///
+/// A block that cannot be read is skipped; the first such error is kept and
+/// reported by `status`.
+pub struct TableIterator<'a, K, R> {
+    table: &'a TableReader<K, R>,
+    index: BlockIter,
+    data: Option<BlockIter>,
+    data_handle: Option<BlockHandle>,
+    // Decoded keys at the restart points of the index and the current data
+    // block, so that seeks do not decode them on every visit.
+    index_restarts: RestartKeys<K>,
+    data_restarts: RestartKeys<K>,
+    key: Option<K>,
+    status: Option<Error>,
+}
+
+impl<'a, K: Ord + KeyCodec, R: Read + Seek> TableIterator<'a, K, R> {
+    pub fn valid(&self) -> bool {
+        self.key.is_some()
+    }
+
+    pub fn key(&self) -> &K {
+        self.key.as_ref().expect("iterator is not valid")
+    }
+
+    /// The first error encountered while reading the table, if any.
+    pub fn status(&self) -> Option<&Error> {
+        self.status.as_ref()
+    }
+
+    pub fn next(&mut self) {
+        assert!(self.valid());
+        self.data.as_mut().unwrap().next();
+        self.skip_empty_blocks_forward();
+    }
+
+    pub fn prev(&mut self) {
+        assert!(self.valid());
+        self.data.as_mut().unwrap().prev();
+        self.skip_empty_blocks_backward();
+    }
+
+    pub fn seek<Q>(&mut self, target: &Q)
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.index.seek(|restart, k| self.index_restarts.compare(restart, k, target));
+        self.init_data_block();
+        if let Some(data) = &mut self.data {
+            data.seek(|restart, k| self.data_restarts.compare(restart, k, target));
+        }
+        self.skip_empty_blocks_forward();
+    }
+
+    pub fn seek_to_first(&mut self) {
+        self.index.seek_to_first();
+        self.init_data_block();
+        if let Some(data) = &mut self.data {
+            data.seek_to_first();
+        }
+        self.skip_empty_blocks_forward();
+    }
+
+    pub fn seek_to_last(&mut self) {
+        self.index.seek_to_last();
+        self.init_data_block();
+        if let Some(data) = &mut self.data {
+            data.seek_to_last();
+        }
+        self.skip_empty_blocks_backward();
+    }
+
+    fn init_data_block(&mut self) {
+        if !self.index.valid() {
+            self.data = None;
+            self.data_handle = None;
+            return;
+        }
+        let handle = match BlockHandle::decode_from(&mut self.index.value()) {
+            Ok(handle) => handle,
+            Err(e) => {
+                self.set_error(e);
+                self.data = None;
+                self.data_handle = None;
+                return;
+            }
+        };
+        if self.data.is_some() && self.data_handle == Some(handle) {
+            return;
+        }
+        self.data_handle = Some(handle);
+        self.data_restarts.clear();
+        self.data = match self.table.read_block(&handle) {
+            Ok(block) => Some(block.iter()),
+            Err(e) => {
+                self.set_error(e);
+                None
+            }
+        };
+    }
+
+    fn skip_empty_blocks_forward(&mut self) {
+        while !self.data.as_ref().map_or(false, |d| d.valid()) {
+            self.check_block_corruption();
+            if !self.index.valid() {
+                self.data = None;
+                break;
+            }
+            self.index.next();
+            self.init_data_block();
+            if let Some(data) = &mut self.data {
+                data.seek_to_first();
+            }
+        }
+        self.update_key();
+    }
+
+    fn skip_empty_blocks_backward(&mut self) {
+        while !self.data.as_ref().map_or(false, |d| d.valid()) {
+            self.check_block_corruption();
+            if !self.index.valid() {
+                self.data = None;
+                break;
+            }
+            self.index.prev();
+            self.init_data_block();
+            if let Some(data) = &mut self.data {
+                data.seek_to_last();
+            }
+        }
+        self.update_key();
+    }
+
+    fn update_key(&mut self) {
+        self.check_block_corruption();
+        self.key = match &self.data {
+            Some(data) if data.valid() => match K::decode(data.key()) {
+                Some(key) => Some(key),
+                None => {
+                    self.set_error(Error::Corruption("bad key in table".into()));
+                    None
+                }
+            },
+            _ => None,
+        };
+    }
+
+    fn check_block_corruption(&mut self) {
+        if self.index.corrupted() || self.data.as_ref().map_or(false, |d| d.corrupted()) {
+            self.set_error(Error::Corruption("bad entry in block".into()));
+        }
+    }
+
+    fn set_error(&mut self, e: Error) {
+        if self.status.is_none() {
+            self.status = Some(e);
+        }
+    }
+}
+
+/// Keys decoded from the restart points of one block, by restart index.
+struct RestartKeys<K> {
+    keys: Vec<Option<K>>,
+}
+
+impl<K> Default for RestartKeys<K> {
+    fn default() -> Self {
+        RestartKeys { keys: Vec::new() }
+    }
+}
+
+impl<K: KeyCodec> RestartKeys<K> {
+    /// Orders an encoded key against a lookup target, decoding a key at a
+    /// restart point only the first time it is compared. Keys that fail to
+    /// decode sort last so that the iterator stops on them and reports the
+    /// corruption.
+    fn compare<Q>(&mut self, restart: Option<usize>, encoded: &[u8], target: &Q) -> Ordering
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        let order = |key: Option<&K>| key.map_or(Ordering::Greater, |k| k.borrow().cmp(target));
+        let Some(index) = restart else {
+            return order(K::decode(encoded).as_ref());
+        };
+        if self.keys.len() <= index {
+            self.keys.resize_with(index + 1, || None);
+        }
+        if self.keys[index].is_none() {
+            self.keys[index] = K::decode(encoded);
+        }
+        order(self.keys[index].as_ref())
+    }
+
+    fn clear(&mut self) {
+        self.keys.clear();
+    }
+}
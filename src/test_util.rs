@@ -0,0 +1,89 @@
+//! Helpers shared by the unit tests of several modules.
+
+use std::fmt::Debug;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::arena::BlockAllocator;
+use crate::SkipListIterator;
+
+/// A path in the temporary directory that is removed when dropped.
+pub(crate) struct TempPath(pub(crate) PathBuf);
+
+impl TempPath {
+    pub(crate) fn new(name: &str) -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let file = format!("skiplist-{}-{}-{}", name, std::process::id(), n);
+        TempPath(std::env::temp_dir().join(file))
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// The cursor interface of the crate's list iterators.
+pub(crate) trait Cursor {
+    type Key;
+
+    fn seek_to_first(&mut self);
+    fn valid(&self) -> bool;
+    fn next(&mut self);
+    fn owned_key(&self) -> Self::Key;
+}
+
+impl<K: Ord + Debug + Default + Clone, A: BlockAllocator> Cursor for SkipListIterator<'_, K, A> {
+    type Key = K;
+
+    fn seek_to_first(&mut self) {
+        SkipListIterator::seek_to_first(self)
+    }
+
+    fn valid(&self) -> bool {
+        SkipListIterator::valid(self)
+    }
+
+    fn next(&mut self) {
+        SkipListIterator::next(self)
+    }
+
+    fn owned_key(&self) -> K {
+        self.key().clone()
+    }
+}
+
+#[cfg(unix)]
+impl Cursor for crate::offset::OffsetSkipListIterator<'_> {
+    type Key = Vec<u8>;
+
+    fn seek_to_first(&mut self) {
+        crate::offset::OffsetSkipListIterator::seek_to_first(self)
+    }
+
+    fn valid(&self) -> bool {
+        crate::offset::OffsetSkipListIterator::valid(self)
+    }
+
+    fn next(&mut self) {
+        crate::offset::OffsetSkipListIterator::next(self)
+    }
+
+    fn owned_key(&self) -> Vec<u8> {
+        self.key().to_vec()
+    }
+}
+
+/// Collects every key of a list, in order, starting from a fresh iterator.
+pub(crate) fn keys<C: Cursor>(mut iter: C) -> Vec<C::Key> {
+    let mut result = Vec::new();
+    iter.seek_to_first();
+    while iter.valid() {
+        result.push(iter.owned_key());
+        iter.next();
+    }
+    result
+}
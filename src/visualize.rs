@@ -0,0 +1,139 @@
+//! Debug renderings of a skip list's levels.
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Write};
+
+use crate::arena::BlockAllocator;
+use crate::{Node, SkipList, SkipListImpl};
+
+impl<K: Ord + Debug + Default, A: BlockAllocator> SkipListImpl<K, A> {
+    /// Nodes in key order, as seen by one pass over level 0.
+    fn nodes(&self) -> Vec<&Node<K>> {
+        let mut nodes = Vec::new();
+        let mut x = unsafe { self.head.as_ref().next(0) };
+        while let Some(node) = unsafe { x.as_ref() } {
+            nodes.push(node);
+            x = node.next(0);
+        }
+        nodes
+    }
+
+    pub fn to_dot(&self) -> String {
+        let nodes = self.nodes();
+        let height = self.get_max_height();
+        let head = self.head.as_ptr() as *const Node<K>;
+        let ids: HashMap<*const Node<K>, usize> =
+            nodes.iter().enumerate().map(|(i, &n)| (n as *const _, i + 1)).chain([(head, 0)]).collect();
+
+        let mut out = String::new();
+        out.push_str("digraph skiplist {\n    rankdir=LR;\n    node [shape=record];\n");
+        let ports = |h: usize| (0..h).rev().map(|l| format!("<l{}>", l)).collect::<Vec<_>>().join("|");
+        writeln!(out, "    n0 [label=\"{{{}|head}}\"];", ports(height)).unwrap();
+        for (i, node) in nodes.iter().enumerate() {
+            let key = escape_record(&format!("{:?}", node.key));
+            writeln!(out, "    n{} [label=\"{{{}|{}}}\"];", i + 1, ports(node.next.len()), key).unwrap();
+        }
+
+        let from = unsafe { self.head.as_ref() };
+        for (id, node) in [(0, from)].into_iter().chain(nodes.iter().enumerate().map(|(i, &n)| (i + 1, n))) {
+            for level in 0..node.next.len().min(height) {
+                // A link to a node published after the level 0 pass is left out.
+                if let Some(&to) = ids.get(&(node.next(level) as *const _)) {
+                    writeln!(out, "    n{}:l{} -> n{}:l{};", id, level, to, level).unwrap();
+                }
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    pub fn to_ascii(&self) -> String {
+        let nodes = self.nodes();
+        let keys: Vec<String> = nodes.iter().map(|n| format!("{:?}", n.key)).collect();
+        let mut out = String::new();
+        for level in (0..self.get_max_height()).rev() {
+            write!(out, "L{:<2} head ", level).unwrap();
+            for (node, key) in nodes.iter().zip(&keys) {
+                if node.next.len() > level {
+                    write!(out, "-> {} ", key).unwrap();
+                } else {
+                    out.push_str(&"-".repeat(key.len() + 4));
+                }
+            }
+            out.push_str("-> nil\n");
+        }
+        out
+    }
+}
+
+impl<K: Ord + Debug + Default, A: BlockAllocator> SkipList<K, A> {
+    /// Renders every level and its links as a Graphviz graph, keys shown
+    /// with `Debug`. Pipe it through `dot -Tsvg` to view it.
+    pub fn to_dot(&self) -> String {
+        unsafe { (*self.skip_list.get()).to_dot() }
+    }
+
+    /// Draws one line per level, top level first, with each key in its own
+    /// column. Meant for lists small enough to fit on a screen.
+    ///
+    /// ```text
+    /// L1  head ------> 3 -> 5 -> nil
+    /// L0  head -> 1 -> 3 -> 5 -> nil
+    /// ```
+    pub fn to_ascii(&self) -> String {
+        unsafe { (*self.skip_list.get()).to_ascii() }
+    }
+}
+
+fn escape_record(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '"' | '\\' | '{' | '}' | '|' | '<' | '>' | ' ') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arena::Arena;
+    use crate::SkipList;
+
+    #[test]
+    fn ascii() {
+        let list = SkipList::new(Arena::new());
+        assert_eq!(list.to_ascii(), "L0  head -> nil\n");
+
+        for k in [30, 10, 20, 50, 40] {
+            list.insert(k);
+        }
+        let ascii = list.to_ascii();
+        let lines: Vec<&str> = ascii.lines().collect();
+        assert_eq!(lines.len(), list.structure_stats().max_height);
+        assert_eq!(*lines.last().unwrap(), "L0  head -> 10 -> 20 -> 30 -> 40 -> 50 -> nil");
+        for line in &lines {
+            // Columns line up, so every level is as wide as level 0.
+            assert_eq!(line.len(), lines.last().unwrap().len());
+        }
+    }
+
+    #[test]
+    fn dot() {
+        let list = SkipList::new(Arena::new());
+        for i in 0..100 {
+            list.insert(format!("key \"{}\"", i));
+        }
+        let dot = list.to_dot();
+        assert!(dot.starts_with("digraph skiplist {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains(r#"|\"key\ \\\"0\\\"\"}"#));
+
+        // Each node has one incoming link per level of its tower.
+        let stats = list.structure_stats();
+        let links: usize = stats.level_counts.iter().sum();
+        assert_eq!(dot.matches(" -> ").count(), links);
+        assert_eq!(dot.matches("[label=").count(), 101);
+    }
+}